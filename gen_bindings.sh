@@ -36,6 +36,8 @@ bindgen pico-binding/wrapper.hpp \
     -I pico-sdk/src/rp2_common/hardware_spi/include \
     -I pico-sdk/src/rp2_common/hardware_sync/include \
     -I pico-sdk/src/rp2_common/hardware_rtc/include \
+    -I pico-sdk/src/rp2_common/hardware_flash/include \
+    -I pico-sdk/src/rp2_common/pico_unique_id/include \
     -I pico-sdk/src/rp2040/hardware_regs/include \
     -I pico-sdk/src/rp2040/hardware_structs/include \
     -I pico-sdk/src/boards/include \
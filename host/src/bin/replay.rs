@@ -0,0 +1,47 @@
+use gocycling_host::replay::{self, Event};
+
+use clap::Parser;
+
+use std::{error::Error, fs, path::PathBuf, time::Duration};
+
+#[derive(Parser)]
+#[command(about = "Decode a capture saved from a GoCycling controller")]
+struct Args {
+    /// Capture file saved with `gocycling save-capture`
+    file: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let file = fs::read(&args.file)?;
+
+    let events = replay::replay(&file).map_err(|err| format!("invalid capture: {}", err))?;
+
+    for event in events {
+        match event {
+            Event::Rx { at_us, result } => match result {
+                Ok(cmd) => println!("{:?} rx {:?}", Duration::from_micros(at_us), cmd),
+                Err(err) => println!(
+                    "{:?} rx invalid command {}: {}",
+                    Duration::from_micros(at_us),
+                    err.cmd,
+                    err.error
+                ),
+            },
+            Event::Tx { at_us, seq, result } => {
+                let seq = seq.map_or(String::new(), |seq| format!(" #{}", seq));
+
+                match result {
+                    Ok(cmd) => println!("{:?} tx{} {:?}", Duration::from_micros(at_us), seq, cmd),
+                    Err(err) => println!(
+                        "{:?} tx invalid frame: {}",
+                        Duration::from_micros(at_us),
+                        err
+                    ),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
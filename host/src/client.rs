@@ -0,0 +1,554 @@
+use crate::{
+    framing::{self, FrameFormat},
+    protocol::{
+        self, signing,
+        update::{self, UPDATE_CHUNK_LEN},
+        BulkCycleData, Capabilities, ErrorCode, ErrorReport, HandshakeReply, Identity, Param,
+        Record, RxCommand, SessionSummary, SignedRecord, Status, TxCommand, UpdateStatus,
+        PUBLIC_KEY_LEN, SIGNATURE_LEN,
+    },
+};
+
+use serialport::SerialPort;
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+/// Time the controller gets to answer a request
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time the controller gets to verify a firmware image
+pub const VERIFY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Times an update chunk is sent before giving up
+const MAX_CHUNK_ATTEMPTS: usize = 5;
+/// Max time a single read on the port blocks, so deadlines are noticed
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Capabilities asked for when the caller doesn't care
+pub const DEFAULT_CAPABILITIES: Capabilities = Capabilities::from_bits_truncate(
+    Capabilities::OFFLINE_SYNC.bits()
+        | Capabilities::RELIABLE_DELIVERY.bits()
+        | Capabilities::CRC16.bits()
+        | Capabilities::SIGNED_RECORDS.bits()
+        | Capabilities::HASH_CHAIN.bits()
+        | Capabilities::LIVE_BATCHING.bits()
+        | Capabilities::ERROR_REPORTS.bits()
+        | Capabilities::BAUD_SWITCHING.bits()
+        | Capabilities::FIRMWARE_UPDATE.bits(),
+);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serial(serialport::Error),
+    Protocol(protocol::Error),
+    /// The controller didn't answer in time
+    Timeout,
+    /// The controller reported that a request failed
+    Rejected(ErrorReport),
+    /// A signed record is not signed with the key of the controller
+    InvalidSignature,
+    /// The request needs a capability that wasn't negotiated
+    Unsupported(Capabilities),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Serial(err) => write!(f, "{}", err),
+            Self::Protocol(err) => write!(f, "{}", err),
+            Self::Timeout => write!(f, "the controller did not answer in time"),
+            Self::Rejected(report) => match ErrorCode::from_u8(report.code) {
+                Some(code) => write!(
+                    f,
+                    "the controller rejected command {} with {:?}",
+                    report.cmd, code
+                ),
+                None => write!(
+                    f,
+                    "the controller rejected command {} with error code {}",
+                    report.cmd, report.code
+                ),
+            },
+            Self::InvalidSignature => write!(f, "the signature of a record is invalid"),
+            Self::Unsupported(capabilities) => {
+                write!(f, "the controller does not support {:?}", capabilities)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(val: io::Error) -> Self {
+        Self::Io(val)
+    }
+}
+
+impl From<serialport::Error> for Error {
+    fn from(val: serialport::Error) -> Self {
+        Self::Serial(val)
+    }
+}
+
+impl From<protocol::Error> for Error {
+    fn from(val: protocol::Error) -> Self {
+        Self::Protocol(val)
+    }
+}
+
+/// Talks to a controller over anything that reads and writes bytes, usually a serial port
+/// or a pty
+pub struct Client<P> {
+    port: P,
+    /// Frame format of sent frames, received frames may use any format
+    format: FrameFormat,
+    capabilities: Capabilities,
+    /// Capabilities asked for in the last handshake
+    requested: Capabilities,
+    /// Sequence number of the next frame when reliable delivery is used
+    expected_seq: u8,
+    /// Key of the controller, signed records are checked against it
+    public_key: Option<[u8; PUBLIC_KEY_LEN]>,
+    rx_buf: Vec<u8>,
+    /// Messages that arrived while waiting for something else
+    backlog: VecDeque<TxCommand>,
+}
+
+impl Client<Box<dyn SerialPort>> {
+    pub fn open(path: &str, baud_rate: u32) -> Result<Self, Error> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(READ_TIMEOUT)
+            .open()?;
+
+        Ok(Self::new(port))
+    }
+}
+
+impl<P: Read + Write> Client<P> {
+    /// Reads from `port` should time out after a short while, so deadlines are noticed
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            // understood by all firmware versions
+            format: FrameFormat::Crc8,
+            capabilities: Capabilities::empty(),
+            requested: Capabilities::empty(),
+            expected_seq: 0,
+            public_key: None,
+            rx_buf: Vec::new(),
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Capabilities both sides support, empty before the handshake
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// The record of `signed` if it's signed with the key of the controller, which is
+    /// fetched during the handshake
+    pub fn verify(&self, signed: &SignedRecord) -> Result<Record, Error> {
+        verify(self.public_key.as_ref(), signed)
+    }
+
+    pub fn send(&mut self, request: &RxCommand) -> Result<(), Error> {
+        let mut frame = [0u8; RxCommand::BUF_SIZE];
+        let frame_len = request.serialize(self.format, &mut frame);
+
+        let mut encoded = [0u8; RxCommand::MAX_ENCODED_LEN];
+        let encoded_len = framing::encode(&frame[..frame_len], &mut encoded).unwrap();
+
+        self.port.write_all(&encoded[..encoded_len])?;
+        self.port.flush()?;
+
+        Ok(())
+    }
+
+    /// Wait for the next message until `deadline`
+    pub fn recv(&mut self, deadline: Instant) -> Result<TxCommand, Error> {
+        if let Some(message) = self.backlog.pop_front() {
+            return Ok(message);
+        }
+
+        loop {
+            if let Some(message) = self.next_buffered_message()? {
+                return Ok(message);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+
+            let mut buf = [0u8; 256];
+            match self.port.read(&mut buf) {
+                Ok(len) => self.rx_buf.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Parse the frames in the receive buffer until one holds a new message
+    fn next_buffered_message(&mut self) -> Result<Option<TxCommand>, Error> {
+        while let Some(end) = self.rx_buf.iter().position(|b| *b == framing::DELIMITER) {
+            let encoded: Vec<u8> = self.rx_buf.drain(..=end).collect();
+            let encoded = &encoded[..end];
+            if encoded.is_empty() {
+                continue;
+            }
+
+            let mut frame = vec![0u8; encoded.len()];
+            let frame = match framing::decode(encoded, &mut frame) {
+                Some(len) => &frame[..len],
+                // a corrupted frame, when reliable delivery is used it will be sent again
+                None => continue,
+            };
+            let (cmd, mut data) = match framing::parse(None, frame) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+
+            let reliable = if cmd == TxCommand::CMD_HANDSHAKE {
+                self.handshake_has_seq(data)
+            } else {
+                self.capabilities.contains(Capabilities::RELIABLE_DELIVERY)
+            };
+
+            if reliable {
+                let (seq, rest) = data.split_first().ok_or(protocol::Error::InvalidLength)?;
+                data = rest;
+
+                if !self.accept_seq(*seq)? {
+                    continue;
+                }
+            }
+
+            return Ok(Some(TxCommand::parse(cmd, data)?));
+        }
+
+        Ok(None)
+    }
+
+    /// The handshake reply is already sent with the negotiated capabilities, which are only
+    /// known after parsing it
+    fn handshake_has_seq(&self, data: &[u8]) -> bool {
+        if !self.requested.contains(Capabilities::RELIABLE_DELIVERY) {
+            return false;
+        }
+
+        let reply = data
+            .get(1..)
+            .map(|data| TxCommand::parse(TxCommand::CMD_HANDSHAKE, data));
+
+        match reply {
+            Some(Ok(TxCommand::Handshake(reply))) => {
+                reply.capabilities.contains(Capabilities::RELIABLE_DELIVERY)
+            }
+            _ => false,
+        }
+    }
+
+    /// Acknowledge frames that arrive in order, returns whether the frame is new
+    fn accept_seq(&mut self, seq: u8) -> Result<bool, Error> {
+        let expected = self.expected_seq;
+
+        if seq == expected {
+            self.expected_seq = seq.wrapping_add(1);
+            self.send(&RxCommand::Ack { seq })?;
+
+            Ok(true)
+        } else {
+            // a duplicate or a frame after a lost one, the controller resends everything
+            // after the last ack so just repeat it
+            self.send(&RxCommand::Ack {
+                seq: expected.wrapping_sub(1),
+            })?;
+
+            Ok(false)
+        }
+    }
+
+    /// Wait for a message `f` returns Some for, other messages are kept for [Client::recv].
+    /// A rejection of request `cmd` is returned as an error.
+    pub fn wait_for<T>(
+        &mut self,
+        cmd: Option<u8>,
+        f: impl FnMut(&TxCommand) -> Option<T>,
+    ) -> Result<T, Error> {
+        self.wait_until(cmd, Instant::now() + RESPONSE_TIMEOUT, f)
+    }
+
+    /// Like [Client::wait_for] with a deadline for requests that take longer
+    pub fn wait_until<T>(
+        &mut self,
+        cmd: Option<u8>,
+        deadline: Instant,
+        mut f: impl FnMut(&TxCommand) -> Option<T>,
+    ) -> Result<T, Error> {
+        let mut skipped = Vec::new();
+
+        let result = loop {
+            let message = match self.recv(deadline) {
+                Ok(message) => message,
+                Err(err) => break Err(err),
+            };
+
+            if let TxCommand::Error(report) = message {
+                if Some(report.cmd) == cmd {
+                    break Err(Error::Rejected(report));
+                }
+            }
+
+            match f(&message) {
+                Some(value) => break Ok(value),
+                None => skipped.push(message),
+            }
+        };
+
+        // keep the order the messages arrived in
+        for message in skipped.into_iter().rev() {
+            self.backlog.push_front(message);
+        }
+
+        result
+    }
+
+    /// Negotiate the capabilities. With `session_active` the controller sends its pending
+    /// offline session and starts a live session, like it does when the app resumes a ride.
+    pub fn handshake(
+        &mut self,
+        session_active: bool,
+        capabilities: Capabilities,
+    ) -> Result<HandshakeReply, Error> {
+        let request = RxCommand::handshake(session_active, capabilities);
+        self.requested = capabilities;
+        // the controller starts a new sequence at 0 when it gets the handshake
+        self.expected_seq = 0;
+        self.send(&request)?;
+
+        let reply = self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::Handshake(reply) => Some(*reply),
+            _ => None,
+        })?;
+
+        self.capabilities = capabilities & reply.capabilities;
+        self.format = if self.capabilities.contains(Capabilities::CRC16) {
+            FrameFormat::Crc16
+        } else {
+            FrameFormat::Crc8
+        };
+
+        if self.capabilities.contains(Capabilities::SIGNED_RECORDS) {
+            // needed to check the records
+            self.identity()?;
+        }
+
+        Ok(reply)
+    }
+
+    pub fn identity(&mut self) -> Result<Identity, Error> {
+        let request = RxCommand::GetIdentity;
+        self.send(&request)?;
+
+        let identity = self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::Identity(identity) => Some(*identity),
+            _ => None,
+        })?;
+        self.public_key = identity.public_key;
+
+        Ok(identity)
+    }
+
+    pub fn status(&mut self) -> Result<Status, Error> {
+        let request = RxCommand::GetStatus;
+        self.send(&request)?;
+
+        self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::Status(status) => Some(*status),
+            _ => None,
+        })
+    }
+
+    /// The controller doesn't answer this unless it fails
+    pub fn start_session(&mut self) -> Result<(), Error> {
+        self.send(&RxCommand::StartSession)
+    }
+
+    pub fn stop_session(&mut self) -> Result<SessionSummary, Error> {
+        let request = RxCommand::StopSession;
+        self.send(&request)?;
+
+        let public_key = self.public_key;
+        self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::SessionSummary(summary) => Some(Ok(*summary)),
+            TxCommand::SignedRecord(signed) => match signed.record {
+                Record::SessionSummary(summary) => {
+                    Some(verify(public_key.as_ref(), signed).map(|_| summary))
+                }
+                _ => None,
+            },
+            _ => None,
+        })?
+    }
+
+    /// Returns the value the controller confirmed
+    pub fn set_param(&mut self, param: Param, value: u32) -> Result<u32, Error> {
+        let request = RxCommand::SetParam { param, value };
+        self.send(&request)?;
+
+        self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::Param(reply) if reply.id == param.id() => Some(reply.value),
+            _ => None,
+        })
+    }
+
+    /// Ask for another baud rate between the controller and the bluetooth module. The
+    /// controller switches after the host disconnected and goes back to the default baud
+    /// rate when the next host doesn't send anything in time.
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), Error> {
+        let request = RxCommand::SetBaudRate { baud_rate };
+        self.send(&request)?;
+
+        self.wait_for(Some(request.cmd()), |message| match message {
+            TxCommand::BaudRate(ack) if ack.baud_rate == baud_rate => Some(()),
+            _ => None,
+        })
+    }
+
+    /// Download the capture file, this stops the capture on the controller
+    pub fn read_capture(&mut self) -> Result<Vec<u8>, Error> {
+        let mut file = Vec::new();
+
+        loop {
+            let offset = file.len() as u16;
+            let request = RxCommand::ReadCapture { offset };
+            self.send(&request)?;
+
+            let chunk = self.wait_for(Some(request.cmd()), |message| match message {
+                TxCommand::CaptureChunk(chunk) if chunk.offset == offset => Some(*chunk),
+                _ => None,
+            })?;
+
+            file.extend_from_slice(chunk.data());
+            if chunk.data().is_empty() || file.len() >= chunk.total_len as usize {
+                return Ok(file);
+            }
+        }
+    }
+
+    /// Send a signed firmware image, the controller stages it for the bootloader once the
+    /// signature checks out. `progress` gets the amount of bytes the controller has so far.
+    pub fn update_firmware(
+        &mut self,
+        image: &[u8],
+        signature: &[u8; SIGNATURE_LEN],
+        mut progress: impl FnMut(usize),
+    ) -> Result<UpdateStatus, Error> {
+        if !self.capabilities.contains(Capabilities::FIRMWARE_UPDATE) {
+            return Err(Error::Unsupported(Capabilities::FIRMWARE_UPDATE));
+        }
+
+        let request = RxCommand::BeginUpdate {
+            image_len: image.len() as u32,
+            digest: update::digest(image),
+        };
+        let mut status = self.request_update(&request, RESPONSE_TIMEOUT)?;
+        let mut attempts = 0;
+
+        while (status.received as usize) < image.len() {
+            let offset = status.received as usize;
+            let end = image.len().min(offset + UPDATE_CHUNK_LEN);
+
+            let mut data = [0xFF; UPDATE_CHUNK_LEN];
+            data[..end - offset].copy_from_slice(&image[offset..end]);
+
+            let request = RxCommand::UpdateChunk {
+                offset: offset as u32,
+                crc: framing::calc_crc16(&data),
+                data,
+            };
+
+            match self.request_update(&request, RESPONSE_TIMEOUT) {
+                Ok(new_status) => {
+                    // the chunk didn't make it when the controller wants it again
+                    if new_status.received > status.received {
+                        attempts = 0;
+                    } else {
+                        attempts += 1;
+                    }
+                    status = new_status;
+                }
+                // lost or corrupted on the way, send it again
+                Err(Error::Timeout) => attempts += 1,
+                Err(Error::Rejected(report)) if report.code == ErrorCode::InvalidChecksum as u8 => {
+                    attempts += 1
+                }
+                Err(err) => return Err(err),
+            }
+
+            if attempts >= MAX_CHUNK_ATTEMPTS {
+                return Err(Error::Timeout);
+            }
+
+            progress(status.received as usize);
+        }
+
+        let request = RxCommand::FinishUpdate {
+            signature: *signature,
+        };
+        self.request_update(&request, VERIFY_TIMEOUT)
+    }
+
+    fn request_update(
+        &mut self,
+        request: &RxCommand,
+        timeout: Duration,
+    ) -> Result<UpdateStatus, Error> {
+        self.send(request)?;
+
+        self.wait_until(
+            Some(request.cmd()),
+            Instant::now() + timeout,
+            |message| match message {
+                TxCommand::UpdateStatus(status) => Some(*status),
+                _ => None,
+            },
+        )
+    }
+
+    /// Wait for the offline session the controller sends after a handshake with
+    /// `session_active`, None if there is no such session
+    pub fn offline_session(&mut self) -> Result<Option<BulkCycleData>, Error> {
+        let public_key = self.public_key;
+        let result = self.wait_for(None, |message| match message {
+            TxCommand::BulkData(data) => Some(Ok(*data)),
+            TxCommand::SignedRecord(signed) => match signed.record {
+                Record::BulkData(data) => Some(verify(public_key.as_ref(), signed).map(|_| data)),
+                _ => None,
+            },
+            _ => None,
+        });
+
+        match result {
+            Ok(data) => data.map(Some),
+            Err(Error::Timeout) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn verify(
+    public_key: Option<&[u8; PUBLIC_KEY_LEN]>,
+    signed: &SignedRecord,
+) -> Result<Record, Error> {
+    match public_key {
+        Some(public_key) if signing::verify(public_key, signed) => Ok(signed.record),
+        // a device without a key doesn't sign anything
+        _ => Err(Error::InvalidSignature),
+    }
+}
@@ -0,0 +1,9 @@
+//! Host side of the protocol between the app and the controller, for tools and tests that
+//! talk to a controller over a serial port.
+
+pub mod client;
+pub mod replay;
+
+pub use client::Client;
+pub use gocycling_protocol as protocol;
+pub use gocycling_protocol::framing;
@@ -0,0 +1,181 @@
+use gocycling_host::{
+    client::{self, DEFAULT_CAPABILITIES},
+    protocol::{Capabilities, ErrorCode, Param, Record, TxCommand, SIGNATURE_LEN},
+    Client,
+};
+
+use clap::{Parser, Subcommand};
+
+use std::{
+    convert::TryInto,
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+#[derive(Parser)]
+#[command(about = "Talk to a GoCycling controller over a serial port")]
+struct Args {
+    /// Serial port or pty the controller is connected to
+    #[arg(short, long)]
+    port: String,
+    #[arg(short, long, default_value_t = 9600)]
+    baud_rate: u32,
+    /// Ask for live cycles as CSC measurements, like a bluetooth bridge does
+    #[arg(long)]
+    csc: bool,
+    /// Ask for indoor bike data every second instead of live cycles
+    #[arg(long)]
+    ftms: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print what the controller is doing
+    Status,
+    /// Start a live session
+    Start,
+    /// Stop the live session and print its summary
+    Stop,
+    /// Print everything the controller sends until interrupted
+    Monitor,
+    /// Print the pending offline session. The controller continues with a live session
+    /// afterwards, like it does when the app resumes a ride.
+    Dump,
+    /// Start recording the traffic on the link, this drops the previous capture
+    Capture,
+    /// Stop recording and save the capture, it can be decoded with gocycling-replay
+    SaveCapture { file: PathBuf },
+    /// Switch the link between the controller and the bluetooth module to another baud
+    /// rate, the controller switches once the host disconnected
+    BaudRate { baud_rate: u32 },
+    /// Send a firmware image, the controller stages it if the signature is valid. Only
+    /// controllers that can install staged images take it, the current firmware can't.
+    Update {
+        image: PathBuf,
+        /// Raw 64 byte `r || s` ECDSA P-256 signature over the SHA-256 of the image
+        signature: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mut client = Client::open(&args.port, args.baud_rate)?;
+
+    let session_active = matches!(args.command, Command::Dump);
+    let mut capabilities = DEFAULT_CAPABILITIES;
+    if args.csc {
+        capabilities |= Capabilities::CSC_MEASUREMENT;
+    }
+    if args.ftms {
+        capabilities |= Capabilities::INDOOR_BIKE_DATA;
+    }
+
+    let reply = client.handshake(session_active, capabilities)?;
+    eprintln!(
+        "protocol version {}, capabilities {:?}",
+        reply.protocol_version,
+        client.capabilities()
+    );
+
+    match args.command {
+        Command::Status => {
+            let status = client.status()?;
+            println!("state: {:?}", status.state);
+            println!("session started: {}", status.started);
+            println!("offline session pending: {}", status.offline_pending);
+            println!("tx buffers: {:?}", status.tx_buf_lens);
+            println!("uptime: {:?}", Duration::from_micros(status.uptime_us));
+            match status.battery_level {
+                Some(level) => println!("battery: {}%", level),
+                None => println!("battery: unknown"),
+            }
+        }
+        Command::Start => client.start_session()?,
+        Command::Stop => {
+            let summary = client.stop_session()?;
+            println!("{:#?}", summary);
+        }
+        Command::Monitor => loop {
+            let deadline = Instant::now() + client::RESPONSE_TIMEOUT;
+            match client.recv(deadline) {
+                Ok(TxCommand::SignedRecord(signed)) => match client.verify(&signed) {
+                    Ok(_) => print_message(&TxCommand::SignedRecord(signed)),
+                    Err(err) => println!("{}: {:?}", err, signed.record),
+                },
+                Ok(message) => print_message(&message),
+                Err(client::Error::Timeout) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        },
+        Command::Dump => match client.offline_session()? {
+            Some(session) => println!("{:#?}", session),
+            None => eprintln!("no offline session pending"),
+        },
+        Command::Capture => {
+            client.set_param(Param::Capture, 1)?;
+        }
+        Command::SaveCapture { file } => {
+            let capture = client.read_capture()?;
+            fs::write(&file, &capture)?;
+            eprintln!("saved {} bytes to {}", capture.len(), file.display());
+        }
+        Command::BaudRate { baud_rate } => {
+            client.set_baud_rate(baud_rate)?;
+            eprintln!(
+                "the controller switches to {} baud after disconnecting",
+                baud_rate
+            );
+        }
+        Command::Update { image, signature } => {
+            let image = fs::read(&image)?;
+            let signature: [u8; SIGNATURE_LEN] = fs::read(&signature)?
+                .as_slice()
+                .try_into()
+                .map_err(|_| "the signature must be 64 bytes")?;
+
+            client.update_firmware(&image, &signature, |received| {
+                eprint!("\rsent {}/{} bytes", received, image.len());
+            })?;
+            eprintln!("\nimage staged for the bootloader");
+        }
+    }
+
+    Ok(())
+}
+
+fn print_message(message: &TxCommand) {
+    match message {
+        TxCommand::LiveData(data) => println!("cycle: {} ms", data.millis),
+        TxCommand::LiveBatch(batch) => {
+            for millis in batch.millis() {
+                println!("cycle: {} ms", millis);
+            }
+        }
+        TxCommand::CscMeasurement(measurement) => println!(
+            "wheel revolutions: {}, last event at {}/1024 s",
+            measurement.wheel_revolutions, measurement.last_wheel_event
+        ),
+        TxCommand::IndoorBikeData(data) => println!(
+            "speed: {:.2} km/h, distance: {} m, elapsed: {} s",
+            f64::from(data.speed) / 100.0,
+            data.distance,
+            data.elapsed_time
+        ),
+        TxCommand::SignedRecord(signed) => match &signed.record {
+            Record::BulkData(data) => println!("signed offline session: {:?}", data),
+            Record::SessionSummary(summary) => println!("signed summary: {:?}", summary),
+        },
+        TxCommand::Error(report) => match ErrorCode::from_u8(report.code) {
+            Some(code) => println!("error: {:?} caused by command {}", code, report.cmd),
+            None => println!(
+                "error: code {} caused by command {}",
+                report.code, report.cmd
+            ),
+        },
+        message => println!("{:?}", message),
+    }
+}
@@ -0,0 +1,115 @@
+//! Decoding of capture files downloaded from the controller, see [protocol::capture] for
+//! the format.
+
+use crate::{
+    framing,
+    protocol::{
+        self,
+        capture::{CaptureReader, Direction},
+        Capabilities, RxCommand, RxError, RxParser, TxCommand, RX_INTERBYTE_TIMEOUT_US,
+    },
+};
+
+#[derive(Debug)]
+pub enum Event {
+    /// A frame the controller received, parsed the way the controller did
+    Rx {
+        at_us: u64,
+        result: Result<RxCommand, RxError>,
+    },
+    /// A frame the controller sent, retransmits show up once per send
+    Tx {
+        at_us: u64,
+        seq: Option<u8>,
+        result: Result<TxCommand, protocol::Error>,
+    },
+}
+
+/// Decode all frames in a capture file, in the order they went over the wire. Fails only
+/// if the file itself is broken, broken frames are returned as events.
+pub fn replay(file: &[u8]) -> Result<Vec<Event>, protocol::Error> {
+    let mut replayer = Replayer {
+        parser: RxParser::new(RX_INTERBYTE_TIMEOUT_US),
+        requested: Capabilities::empty(),
+        capabilities: Capabilities::empty(),
+        events: Vec::new(),
+    };
+
+    for record in CaptureReader::new(file)? {
+        let record = record?;
+
+        match record.direction {
+            Direction::Rx => replayer.rx(record.at_us, record.data),
+            Direction::Tx => replayer.tx(record.at_us, record.data),
+        }
+    }
+
+    Ok(replayer.events)
+}
+
+struct Replayer {
+    parser: RxParser,
+    /// Capabilities asked for in the last handshake
+    requested: Capabilities,
+    /// Capabilities both sides agreed on in the last handshake
+    capabilities: Capabilities,
+    events: Vec<Event>,
+}
+
+impl Replayer {
+    fn rx(&mut self, at_us: u64, data: &[u8]) {
+        // the timestamp is of the first byte, the rest came in right after it
+        for byte in data.iter().copied() {
+            let result = match self.parser.push(byte, at_us, None) {
+                Some(result) => result,
+                None => continue,
+            };
+
+            if let Ok(RxCommand::Handshake { capabilities, .. }) = result {
+                self.requested = capabilities;
+            }
+
+            self.events.push(Event::Rx { at_us, result });
+        }
+    }
+
+    fn tx(&mut self, at_us: u64, data: &[u8]) {
+        for encoded in data.split(|b| *b == framing::DELIMITER) {
+            if encoded.is_empty() {
+                continue;
+            }
+
+            let mut frame = vec![0u8; encoded.len()];
+            let (seq, result) = match framing::decode(encoded, &mut frame) {
+                Some(len) => self.parse_tx(&frame[..len]),
+                None => (None, Err(protocol::Error::InvalidFrame)),
+            };
+
+            if let Ok(TxCommand::Handshake(reply)) = result {
+                self.capabilities = self.requested & reply.capabilities;
+            }
+
+            self.events.push(Event::Tx { at_us, seq, result });
+        }
+    }
+
+    fn parse_tx(&self, frame: &[u8]) -> (Option<u8>, Result<TxCommand, protocol::Error>) {
+        let sequenced = match framing::parse(None, frame) {
+            // the handshake reply is already sent with the negotiated capabilities
+            Some((TxCommand::CMD_HANDSHAKE, _)) => {
+                self.requested.contains(Capabilities::RELIABLE_DELIVERY)
+                    && matches!(
+                        TxCommand::deserialize(frame, None, true),
+                        Ok((_, TxCommand::Handshake(reply)))
+                            if reply.capabilities.contains(Capabilities::RELIABLE_DELIVERY)
+                    )
+            }
+            _ => self.capabilities.contains(Capabilities::RELIABLE_DELIVERY),
+        };
+
+        match TxCommand::deserialize(frame, None, sequenced) {
+            Ok((seq, cmd)) => (seq, Ok(cmd)),
+            Err(err) => (None, Err(err)),
+        }
+    }
+}
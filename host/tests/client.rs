@@ -0,0 +1,239 @@
+use gocycling_host::{
+    client::Error,
+    framing::{self, FrameFormat},
+    protocol::{
+        Capabilities, HandshakeReply, Pong, RxCommand, TxCommand, PROTOCOL_VERSION, SIGNATURE_LEN,
+    },
+    Client,
+};
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+const CAPABILITIES: Capabilities = Capabilities::from_bits_truncate(
+    Capabilities::RELIABLE_DELIVERY.bits() | Capabilities::CRC16.bits(),
+);
+
+/// The controller side of an in-memory serial port
+#[derive(Clone, Default)]
+struct FakePort {
+    /// Bytes the client reads next
+    to_client: Rc<RefCell<VecDeque<u8>>>,
+    /// Bytes the client wrote
+    from_client: Rc<RefCell<Vec<u8>>>,
+}
+
+impl FakePort {
+    fn send(&self, cmd: TxCommand, seq: u8) {
+        let mut frame = [0u8; TxCommand::MAX_FRAME_LEN];
+        let frame = cmd
+            .serialize(FrameFormat::Crc16, Some(seq), &mut frame)
+            .unwrap();
+
+        let mut encoded = [0u8; framing::max_encoded_len(TxCommand::MAX_FRAME_LEN)];
+        let encoded_len = framing::encode(frame, &mut encoded).unwrap();
+
+        self.to_client
+            .borrow_mut()
+            .extend(encoded[..encoded_len].iter().copied());
+    }
+
+    fn send_pong(&self, nonce: u32, seq: u8) {
+        self.send(TxCommand::Pong(Pong { nonce }), seq);
+    }
+
+    /// Requests the client sent since the last call
+    fn received(&self) -> Vec<RxCommand> {
+        let raw: Vec<u8> = self.from_client.borrow_mut().drain(..).collect();
+
+        raw.split(|b| *b == framing::DELIMITER)
+            .filter(|encoded| !encoded.is_empty())
+            .map(|encoded| {
+                let mut frame = vec![0u8; encoded.len()];
+                let len = framing::decode(encoded, &mut frame).unwrap();
+                RxCommand::deserialize(&frame[..len], None).unwrap()
+            })
+            .collect()
+    }
+
+    fn acks(&self) -> Vec<u8> {
+        self.received()
+            .into_iter()
+            .filter_map(|request| match request {
+                RxCommand::Ack { seq } => Some(seq),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Read for FakePort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut to_client = self.to_client.borrow_mut();
+        if to_client.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let len = buf.len().min(to_client.len());
+        for (dst, src) in buf.iter_mut().zip(to_client.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for FakePort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.from_client.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn handshake_reply() -> TxCommand {
+    TxCommand::Handshake(HandshakeReply {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: CAPABILITIES,
+    })
+}
+
+/// A client that completed the handshake, the reply used sequence number 0
+fn connected_client() -> (Client<FakePort>, FakePort) {
+    let port = FakePort::default();
+    let mut client = Client::new(port.clone());
+
+    port.send(handshake_reply(), 0);
+    client.handshake(false, CAPABILITIES).unwrap();
+    assert_eq!(port.acks(), [0]);
+
+    (client, port)
+}
+
+fn recv(client: &mut Client<FakePort>) -> Result<TxCommand, Error> {
+    client.recv(Instant::now() + Duration::from_millis(50))
+}
+
+#[test]
+fn negotiates_capabilities() {
+    let port = FakePort::default();
+    let mut client = Client::new(port.clone());
+
+    port.send(handshake_reply(), 0);
+    client
+        .handshake(false, CAPABILITIES | Capabilities::HEARTBEAT)
+        .unwrap();
+
+    assert_eq!(client.capabilities(), CAPABILITIES);
+    assert!(matches!(port.received()[0], RxCommand::Handshake { .. }));
+}
+
+#[test]
+fn acks_frames_in_order() {
+    let (mut client, port) = connected_client();
+    port.send_pong(1, 1);
+    port.send_pong(2, 2);
+
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 1 })
+    );
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 2 })
+    );
+    assert_eq!(port.acks(), [1, 2]);
+}
+
+#[test]
+fn does_not_ack_past_a_lost_frame() {
+    let (mut client, port) = connected_client();
+    // frame 1 got lost
+    port.send_pong(2, 2);
+
+    assert!(matches!(recv(&mut client), Err(Error::Timeout)));
+    assert_eq!(port.acks(), [0]);
+
+    // the controller resends everything after the last ack
+    port.send_pong(1, 1);
+    port.send_pong(2, 2);
+
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 1 })
+    );
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 2 })
+    );
+    assert_eq!(port.acks(), [1, 2]);
+}
+
+#[test]
+fn drops_duplicates() {
+    let (mut client, port) = connected_client();
+    port.send_pong(1, 1);
+    // the ack got lost, so the controller sent it again
+    port.send_pong(1, 1);
+
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 1 })
+    );
+    assert!(matches!(recv(&mut client), Err(Error::Timeout)));
+    assert_eq!(port.acks(), [1, 1]);
+}
+
+#[test]
+fn starts_a_new_sequence_after_a_handshake() {
+    let (mut client, port) = connected_client();
+    port.send_pong(1, 1);
+    assert!(recv(&mut client).is_ok());
+    port.received();
+
+    port.send(handshake_reply(), 0);
+    client.handshake(false, CAPABILITIES).unwrap();
+    port.send_pong(2, 1);
+
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 2 })
+    );
+    assert_eq!(port.acks(), [0, 1]);
+}
+
+#[test]
+fn does_not_ack_past_a_lost_first_frame() {
+    let (mut client, port) = connected_client();
+
+    // the reply to the second handshake got lost, the frame after it arrives first
+    port.send_pong(1, 1);
+    port.send(handshake_reply(), 0);
+    port.send_pong(1, 1);
+    client.handshake(false, CAPABILITIES).unwrap();
+
+    assert_eq!(
+        recv(&mut client).unwrap(),
+        TxCommand::Pong(Pong { nonce: 1 })
+    );
+    assert_eq!(port.acks(), [u8::MAX, 0, 1]);
+}
+
+#[test]
+fn updates_need_the_capability() {
+    let (mut client, port) = connected_client();
+
+    let result = client.update_firmware(&[0; 16], &[0; SIGNATURE_LEN], |_| ());
+
+    assert!(
+        matches!(result, Err(Error::Unsupported(capabilities)) if capabilities == Capabilities::FIRMWARE_UPDATE)
+    );
+    assert!(port.received().is_empty());
+}
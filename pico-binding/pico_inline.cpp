@@ -38,6 +38,22 @@ uint8_t binding_uart_getc(void *uart) {
     return uart_getc((uart_inst_t *)uart);
 }
 
+bool binding_uart_is_writable(void *uart) {
+    return uart_is_writable((uart_inst_t *)uart);
+}
+
+void binding_uart_putc_raw(void *uart, uint8_t c) {
+    uart_putc_raw((uart_inst_t *)uart, c);
+}
+
+uint binding_uart_set_baudrate(void *uart, uint baud_rate) {
+    return uart_set_baudrate((uart_inst_t *)uart, baud_rate);
+}
+
+void binding_uart_tx_wait_blocking(void *uart) {
+    uart_tx_wait_blocking((uart_inst_t *)uart);
+}
+
 
 void binding_irq_set_exclusive_handler(uint irq, void (*fn)()) {
     irq_set_exclusive_handler(irq, fn);
@@ -85,3 +101,42 @@ uint32_t binding_save_and_disable_interrupts() {
 void binding_restore_interrupts(uint32_t status) {
     restore_interrupts(status);
 }
+
+
+void binding_flash_range_erase(uint32_t flash_offs, uint count) {
+    // the flash can't be read while it's being written, make sure no interrupt
+    // handler gets executed from it
+    uint32_t status = save_and_disable_interrupts();
+    flash_range_erase(flash_offs, count);
+    restore_interrupts(status);
+}
+
+void binding_flash_range_program(uint32_t flash_offs, const uint8_t *data, uint count) {
+    uint32_t status = save_and_disable_interrupts();
+    flash_range_program(flash_offs, data, count);
+    restore_interrupts(status);
+}
+
+const uint8_t *binding_flash_contents(uint32_t flash_offs) {
+    return (const uint8_t *)(XIP_BASE + flash_offs);
+}
+
+
+void binding_get_unique_board_id(uint8_t *id) {
+    pico_unique_board_id_t board_id;
+    pico_get_unique_board_id(&board_id);
+
+    for (uint i = 0; i < PICO_UNIQUE_BOARD_ID_SIZE_BYTES; i++) {
+        id[i] = board_id.id[i];
+    }
+}
+
+uint8_t binding_rosc_random_byte() {
+    uint8_t value = 0;
+
+    for (uint i = 0; i < 8; i++) {
+        value = (value << 1) | (rosc_hw->randombit & 1);
+    }
+
+    return value;
+}
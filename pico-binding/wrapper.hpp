@@ -7,6 +7,9 @@
 #include "hardware/gpio.h"
 #include "hardware/rtc.h"
 #include "hardware/pwm.h"
+#include "hardware/flash.h"
+#include "hardware/structs/rosc.h"
+#include "pico/unique_id.h"
 
 extern "C" void *binding_uart0_init(uint baud_rate, uint tx_pin, uint rx_pin);
 extern "C" void binding_uart_destroy(void* uart);
@@ -15,6 +18,10 @@ extern "C" void binding_uart_read_blocking(void *uart, uint8_t *data, uint len);
 extern "C" void binding_uart_set_irq_enables(void *uart, bool rx, bool tx);
 extern "C" bool binding_uart_is_readable(void *uart);
 extern "C" uint8_t binding_uart_getc(void *uart);
+extern "C" bool binding_uart_is_writable(void *uart);
+extern "C" void binding_uart_putc_raw(void *uart, uint8_t c);
+extern "C" uint binding_uart_set_baudrate(void *uart, uint baud_rate);
+extern "C" void binding_uart_tx_wait_blocking(void *uart);
 
 extern "C" void binding_irq_set_exclusive_handler(uint irq, void (*fn)());
 extern "C" void binding_irq_set_enabled(uint irq, bool enabled);
@@ -30,3 +37,10 @@ extern "C" void binding_pwm_set_gpio_level(uint gpio, uint16_t level);
 
 extern "C" uint32_t binding_save_and_disable_interrupts();
 extern "C" void binding_restore_interrupts(uint32_t status);
+
+extern "C" void binding_flash_range_erase(uint32_t flash_offs, uint count);
+extern "C" void binding_flash_range_program(uint32_t flash_offs, const uint8_t *data, uint count);
+extern "C" const uint8_t *binding_flash_contents(uint32_t flash_offs);
+
+extern "C" void binding_get_unique_board_id(uint8_t *id);
+extern "C" uint8_t binding_rosc_random_byte();
@@ -0,0 +1,45 @@
+//! Bytes from the host with the time they arrived, fed to the parser the way the UART
+//! interrupt does. After the input, a valid frame must always come through.
+
+#![no_main]
+
+use gocycling_protocol::{
+    framing::{self, FrameFormat},
+    RxCommand, RxParser,
+};
+use libfuzzer_sys::fuzz_target;
+
+const TIMEOUT_US: u64 = 200_000;
+
+fuzz_target!(|input: (bool, Vec<(u8, u16)>)| {
+    let (crc16, bytes) = input;
+    let format = if crc16 {
+        FrameFormat::Crc16
+    } else {
+        FrameFormat::Crc8
+    };
+
+    let mut parser = RxParser::new(TIMEOUT_US);
+    let mut now_us = 0u64;
+
+    for (byte, gap_us) in bytes {
+        now_us += u64::from(gap_us);
+        parser.push(byte, now_us, None);
+    }
+
+    let cmd = RxCommand::Ping { nonce: 0x1234_5678 };
+    let mut frame = [0u8; RxCommand::BUF_SIZE];
+    let frame_len = cmd.serialize(format, &mut frame);
+    let mut encoded = [0u8; RxCommand::MAX_ENCODED_LEN];
+    let encoded_len = framing::encode(&frame[..frame_len], &mut encoded).unwrap();
+
+    // end whatever frame the input left behind
+    parser.push(framing::DELIMITER, now_us, None);
+
+    let mut result = None;
+    for byte in encoded[..encoded_len].iter().copied() {
+        result = parser.push(byte, now_us, Some(format));
+    }
+
+    assert_eq!(result.unwrap().unwrap(), cmd);
+});
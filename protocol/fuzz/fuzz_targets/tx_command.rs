@@ -0,0 +1,12 @@
+//! Decoded frames from the controller as the host tools parse them
+
+#![no_main]
+
+use gocycling_protocol::TxCommand;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((sequenced, raw)) = data.split_first() {
+        TxCommand::deserialize(raw, None, sequenced & 1 != 0).ok();
+    }
+});
@@ -0,0 +1,250 @@
+//! The bluetooth module is configured with AT commands over the same UART that carries
+//! the host link, so this only works while no host is connected. A command like
+//! `AT+NAME=GoCycling` is answered with `OK+NAME=GoCycling` and there is no line
+//! terminator, a response ends when the module stops sending.
+//!
+//! The command logic only talks to an [AtPort], so it can be run against a scripted fake
+//! module instead of the real UART.
+//!
+//! A baud rate the host asks for is applied by [BaudRateSwitch] after the host
+//! disconnected, the link uses it from the next connection on.
+
+use arrayvec::{ArrayString, ArrayVec};
+use core::fmt::Write;
+
+/// Max time the module gets to start a response
+const RESPONSE_TIMEOUT_US: u64 = 500_000;
+/// A response is complete when no byte arrives within this time
+const RESPONSE_END_US: u64 = 20_000;
+/// Amount of times a command is sent before giving up
+const MAX_ATTEMPTS: usize = 3;
+/// Baud rates the module can be configured with, the factory default comes first
+pub const BAUD_RATES: [u32; 5] = [9600, 19200, 38400, 57600, 115200];
+pub const DEFAULT_BAUD_RATE: u32 = BAUD_RATES[0];
+/// Time a host gets to send a valid frame after connecting at another baud rate than
+/// [DEFAULT_BAUD_RATE], after that the default one is restored
+pub const BAUD_FALLBACK_TIMEOUT_US: u64 = 3_000_000;
+
+const MAX_RESPONSE_LEN: usize = 32;
+
+/// Settings applied at boot, the module stores them so this only changes something
+/// the first time. The module only starts executing a command after it sent the response,
+/// so each command comes with the time the module needs before it takes the next one.
+/// The name goes last, a module with [CONFIGURED_NAME] went through all of them.
+const SETUP_COMMANDS: [(&[u8], u64); 3] = [
+    // turn off the onboard led
+    (b"AT+LED2M=1", 1_000_000),
+    // advertising interval in units of 100ms
+    (b"AT+ADVIN=5", 50_000),
+    (b"AT+NAME=GoCycling", 50_000),
+];
+/// Response to `AT+NAME` once [SETUP_COMMANDS] were applied
+const CONFIGURED_NAME: &[u8] = b"+NAME=GoCycling";
+
+pub type Response = ArrayVec<u8, MAX_RESPONSE_LEN>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The module didn't answer in time
+    Timeout,
+    /// The module answered with something other than `OK`
+    Rejected,
+    ResponseTooLong,
+    /// The module doesn't answer at any of the [BAUD_RATES]
+    UnknownBaudRate,
+    /// Not one of the [BAUD_RATES]
+    UnsupportedBaudRate,
+}
+
+/// Byte level access to the module
+pub trait AtPort {
+    fn write(&mut self, data: &[u8]);
+    /// Wait at most `timeout_us` for a byte
+    fn read_byte(&mut self, timeout_us: u64) -> Option<u8>;
+    fn set_baud_rate(&mut self, baud_rate: u32);
+    fn sleep_us(&mut self, duration_us: u64);
+}
+
+pub struct AtManager<P: AtPort> {
+    port: P,
+}
+
+impl<P: AtPort> AtManager<P> {
+    pub fn new(port: P) -> Self {
+        Self { port }
+    }
+
+    /// Send a command, retrying when it fails. Returns the response after `OK`.
+    pub fn command(&mut self, cmd: &[u8]) -> Result<Response, Error> {
+        let mut result = Err(Error::Timeout);
+
+        for _ in 0..MAX_ATTEMPTS {
+            result = self.try_command(cmd);
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        result
+    }
+
+    fn try_command(&mut self, cmd: &[u8]) -> Result<Response, Error> {
+        // drop leftovers of an earlier response that came in too late
+        while self.port.read_byte(0).is_some() {}
+
+        self.port.write(cmd);
+
+        let mut response = Response::new();
+        let mut timeout_us = RESPONSE_TIMEOUT_US;
+        while let Some(byte) = self.port.read_byte(timeout_us) {
+            response
+                .try_push(byte)
+                .map_err(|_| Error::ResponseTooLong)?;
+            timeout_us = RESPONSE_END_US;
+        }
+
+        if response.is_empty() {
+            return Err(Error::Timeout);
+        }
+
+        parse_response(&response).map(|rest| rest.iter().copied().collect())
+    }
+
+    /// Find the baud rate the module currently uses and switch to it
+    pub fn detect_baud_rate(&mut self) -> Result<u32, Error> {
+        for baud_rate in BAUD_RATES.iter().copied() {
+            self.port.set_baud_rate(baud_rate);
+
+            if self.try_command(b"AT").is_ok() {
+                return Ok(baud_rate);
+            }
+        }
+
+        Err(Error::UnknownBaudRate)
+    }
+
+    /// Reconfigure the module to use `baud_rate` and switch to it
+    pub fn change_baud_rate(&mut self, baud_rate: u32) -> Result<(), Error> {
+        if !BAUD_RATES.contains(&baud_rate) {
+            return Err(Error::UnsupportedBaudRate);
+        }
+
+        let mut cmd = ArrayString::<16>::new();
+        // fits any of the supported baud rates
+        write!(cmd, "AT+BAUD={}", baud_rate).unwrap();
+
+        // the module answers at the old baud rate and switches right after
+        self.command(cmd.as_bytes())?;
+        self.port.set_baud_rate(baud_rate);
+
+        Ok(())
+    }
+
+    /// Whether the module already has the settings in [SETUP_COMMANDS]
+    pub fn configured(&mut self) -> Result<bool, Error> {
+        Ok(&self.command(b"AT+NAME")?[..] == CONFIGURED_NAME)
+    }
+
+    /// Apply the settings in [SETUP_COMMANDS] unless the module has them already, stops at
+    /// the first command that fails. Skipping them saves the sleeps and the flash writes
+    /// of the module on every boot.
+    pub fn setup(&mut self) -> Result<(), Error> {
+        if let Ok(true) = self.configured() {
+            return Ok(());
+        }
+
+        for (cmd, duration_us) in SETUP_COMMANDS.iter() {
+            self.command(cmd)?;
+            self.port.sleep_us(*duration_us);
+        }
+
+        Ok(())
+    }
+
+    pub fn into_port(self) -> P {
+        self.port
+    }
+}
+
+/// Decides when the link to the module switches to another baud rate. The module only
+/// takes AT commands while no host is connected, so a requested baud rate waits until the
+/// module is idle. When a host connects and can't reach us at the current baud rate, the
+/// next switch goes back to [DEFAULT_BAUD_RATE].
+pub struct BaudRateSwitch {
+    baud_rate: u32,
+    pending: Option<u32>,
+    /// Set while a connected host didn't send a valid frame yet
+    fallback_at_us: Option<u64>,
+}
+
+impl BaudRateSwitch {
+    /// `baud_rate` is the one the module currently uses
+    pub const fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            pending: None,
+            fallback_at_us: None,
+        }
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    /// Switch to `baud_rate` the next time the module is idle
+    pub fn request(&mut self, baud_rate: u32) -> Result<(), Error> {
+        if !BAUD_RATES.contains(&baud_rate) {
+            return Err(Error::UnsupportedBaudRate);
+        }
+
+        self.pending = Some(baud_rate);
+        Ok(())
+    }
+
+    /// A host connected to the module
+    pub fn connected(&mut self, now_us: u64) {
+        if self.baud_rate != DEFAULT_BAUD_RATE {
+            self.fallback_at_us = Some(now_us + BAUD_FALLBACK_TIMEOUT_US);
+        }
+    }
+
+    /// The host can reach us at the current baud rate
+    pub fn frame_received(&mut self) {
+        self.fallback_at_us = None;
+    }
+
+    /// The baud rate to switch to now, if any. `idle` tells whether the module takes AT
+    /// commands and no frame is being sent.
+    pub fn poll(&mut self, now_us: u64, idle: bool) -> Option<u32> {
+        if self.fallback_at_us.is_some_and(|at| now_us >= at) {
+            self.fallback_at_us = None;
+            self.pending = Some(DEFAULT_BAUD_RATE);
+        }
+
+        if !idle {
+            return None;
+        }
+
+        self.pending
+            .take()
+            .filter(|&baud_rate| baud_rate != self.baud_rate)
+    }
+
+    /// Record the baud rate the module ended up with after a switch
+    pub fn switched(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+}
+
+/// Check a complete response, returns what comes after `OK` without line endings
+pub fn parse_response(raw: &[u8]) -> Result<&[u8], Error> {
+    let mut end = raw.len();
+    while end > 0 && (raw[end - 1] == b'\r' || raw[end - 1] == b'\n') {
+        end -= 1;
+    }
+
+    match raw[..end].strip_prefix(b"OK") {
+        Some(rest) => Ok(rest),
+        None => Err(Error::Rejected),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::Error;
+
+pub const MAX_BATCH_LEN: usize = 16;
+/// Bytes a u32 takes at most as a varint
+const MAX_VARINT_LEN: usize = 5;
+
+/// Several cycles sent in a single frame. On the wire it's the amount of cycles followed
+/// by the millis of the first cycle, every next cycle is the difference to the previous
+/// one, all as postcard varints. Like postcard does for signed integers, the differences
+/// are zigzag encoded first.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CycleBatch {
+    millis: [u32; MAX_BATCH_LEN],
+    len: u8,
+}
+
+impl CycleBatch {
+    pub const fn new() -> Self {
+        Self {
+            millis: [0; MAX_BATCH_LEN],
+            len: 0,
+        }
+    }
+
+    /// Returns false if the batch is full
+    pub fn push(&mut self, millis: u32) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.millis[self.len as usize] = millis;
+        self.len += 1;
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len as usize == MAX_BATCH_LEN
+    }
+
+    /// Millis of each cycle in the batch
+    pub fn millis(&self) -> &[u32] {
+        &self.millis[..self.len as usize]
+    }
+
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let mut used = write_varint(u32::from(self.len), buf)?;
+        let mut prev = 0;
+
+        for millis in self.millis().iter().copied() {
+            let delta = millis.wrapping_sub(prev) as i32;
+            used += write_varint(zigzag(delta), &mut buf[used..])?;
+            prev = millis;
+        }
+
+        Ok(used)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        let (len, mut rest) = read_varint(data)?;
+        if len as usize > MAX_BATCH_LEN {
+            return Err(Error::InvalidLength);
+        }
+
+        let mut batch = Self::new();
+        let mut prev = 0u32;
+
+        for _ in 0..len {
+            let (delta, next) = read_varint(rest)?;
+            prev = prev.wrapping_add(unzigzag(delta) as u32);
+            batch.push(prev);
+            rest = next;
+        }
+
+        if !rest.is_empty() {
+            return Err(Error::InvalidLength);
+        }
+
+        Ok(batch)
+    }
+}
+
+/// Write `value` 7 bits at a time starting with the lowest ones, the high bit of a byte
+/// tells whether another one follows
+fn write_varint(mut value: u32, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+    let mut used = 0;
+
+    loop {
+        let byte = buf
+            .get_mut(used)
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+        *byte = (value & 0x7F) as u8;
+        value >>= 7;
+        used += 1;
+
+        if value == 0 {
+            return Ok(used);
+        }
+        *byte |= 0x80;
+    }
+}
+
+fn read_varint(data: &[u8]) -> Result<(u32, &[u8]), postcard::Error> {
+    let mut value = 0u32;
+
+    for (i, byte) in data.iter().copied().enumerate().take(MAX_VARINT_LEN) {
+        // the last byte only has room for the top 4 bits
+        if i == MAX_VARINT_LEN - 1 && byte > 0x0F {
+            return Err(postcard::Error::DeserializeBadVarint);
+        }
+
+        value |= u32::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, &data[i + 1..]));
+        }
+    }
+
+    Err(postcard::Error::DeserializeUnexpectedEnd)
+}
+
+/// Small negative numbers become small positive ones, so they make short varints
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
@@ -0,0 +1,210 @@
+//! Recording of the traffic on the UART link, so a session that went wrong in the field can
+//! be replayed against the decoder later.
+//!
+//! A capture file starts with the magic `GCAP` and a version byte, currently 1. Records
+//! follow until the end of the file, oldest first:
+//!
+//! | Field     | Size | Description                                                   |
+//! |-----------|------|---------------------------------------------------------------|
+//! | direction | 1    | 0 for bytes received by the controller, 1 for sent bytes      |
+//! | at_us     | 8    | Little endian microseconds since the controller booted        |
+//! | len       | 2    | Little endian length of the data                              |
+//! | data      | len  | Bytes as they went over the wire, still encoded               |
+//!
+//! Received bytes are recorded the way they came in, including garbage and delimiters, so
+//! they have to be fed through an [crate::RxParser]. Sent bytes are recorded per frame
+//! when the frame is queued, retransmits show up again.
+
+use crate::Error;
+
+use core::convert::TryInto;
+
+pub const CAPTURE_MAGIC: [u8; 4] = *b"GCAP";
+pub const CAPTURE_VERSION: u8 = 1;
+/// Magic and version
+pub const FILE_HEADER_LEN: usize = 5;
+/// Direction, timestamp and data length
+pub const RECORD_HEADER_LEN: usize = 11;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Host to controller
+    Rx = 0,
+    /// Controller to host
+    Tx = 1,
+}
+
+impl Direction {
+    pub fn from_u8(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Self::Rx),
+            1 => Some(Self::Tx),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps the newest records that fit in `N` bytes, older records are dropped to make room.
+/// The contents are read back in the capture file format.
+pub struct CaptureRing<const N: usize> {
+    buf: [u8; N],
+    /// Position of the oldest record
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> CaptureRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Returns false if the record is larger than the whole ring
+    pub fn push(&mut self, direction: Direction, at_us: u64, data: &[u8]) -> bool {
+        let record_len = RECORD_HEADER_LEN + data.len();
+        if record_len > N || data.len() > u16::MAX as usize {
+            return false;
+        }
+
+        while N - self.len < record_len {
+            self.drop_oldest();
+        }
+
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0] = direction as u8;
+        header[1..9].copy_from_slice(&at_us.to_le_bytes());
+        header[9..11].copy_from_slice(&(data.len() as u16).to_le_bytes());
+
+        self.write(&header);
+        self.write(data);
+
+        true
+    }
+
+    /// Size of the contents in the capture file format
+    pub fn file_len(&self) -> usize {
+        FILE_HEADER_LEN + self.len
+    }
+
+    /// Copy the contents in the capture file format starting at `offset`, returns the
+    /// amount of bytes copied
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let end = self.file_len().min(offset.saturating_add(buf.len()));
+        if offset >= end {
+            return 0;
+        }
+
+        for (dst, pos) in buf.iter_mut().zip(offset..end) {
+            *dst = match pos {
+                0..=3 => CAPTURE_MAGIC[pos],
+                4 => CAPTURE_VERSION,
+                _ => self.byte(pos - FILE_HEADER_LEN),
+            };
+        }
+
+        end - offset
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().copied() {
+            self.buf[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn byte(&self, idx: usize) -> u8 {
+        self.buf[(self.start + idx) % N]
+    }
+
+    fn drop_oldest(&mut self) {
+        let data_len = u16::from_le_bytes([self.byte(9), self.byte(10)]) as usize;
+        let record_len = RECORD_HEADER_LEN + data_len;
+
+        self.start = (self.start + record_len) % N;
+        self.len -= record_len;
+    }
+}
+
+impl<const N: usize> Default for CaptureRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureRecord<'a> {
+    pub direction: Direction,
+    pub at_us: u64,
+    pub data: &'a [u8],
+}
+
+/// Iterates over the records of a capture file
+pub struct CaptureReader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> CaptureReader<'a> {
+    /// Fails if the file doesn't start with a header this crate understands
+    pub fn new(file: &'a [u8]) -> Result<Self, Error> {
+        if file.len() < FILE_HEADER_LEN {
+            return Err(Error::InvalidLength);
+        }
+        if file[..4] != CAPTURE_MAGIC || file[4] != CAPTURE_VERSION {
+            return Err(Error::InvalidValue);
+        }
+
+        Ok(Self {
+            rest: &file[FILE_HEADER_LEN..],
+        })
+    }
+
+    fn next_record(&mut self) -> Result<CaptureRecord<'a>, Error> {
+        if self.rest.len() < RECORD_HEADER_LEN {
+            return Err(Error::InvalidLength);
+        }
+
+        let (header, rest) = self.rest.split_at(RECORD_HEADER_LEN);
+        let direction = Direction::from_u8(header[0]).ok_or(Error::InvalidValue)?;
+        let at_us = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let data_len = u16::from_le_bytes([header[9], header[10]]) as usize;
+
+        if rest.len() < data_len {
+            return Err(Error::InvalidLength);
+        }
+
+        let (data, rest) = rest.split_at(data_len);
+        self.rest = rest;
+
+        Ok(CaptureRecord {
+            direction,
+            at_us,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for CaptureReader<'a> {
+    type Item = Result<CaptureRecord<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let result = self.next_record();
+        if result.is_err() {
+            // the rest can't be trusted after a broken record
+            self.rest = &[];
+        }
+
+        Some(result)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::Error;
+
+/// Wheel revolution data in the layout of the Bluetooth Cycling Speed and Cadence
+/// "CSC Measurement" characteristic, so a bridge with a GATT server can forward it
+/// verbatim. On the wire it's the flags byte, the cumulative wheel revolutions as a little
+/// endian u32 and the last wheel event time as a little endian u16.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CscMeasurement {
+    /// Wraps around, collectors only look at the difference to the previous measurement
+    pub wheel_revolutions: u32,
+    /// Time of the last revolution in 1/1024 s, wraps around every 64 seconds
+    pub last_wheel_event: u16,
+}
+
+impl CscMeasurement {
+    /// Encoded length, the crank revolution data is never present
+    pub const LEN: usize = 7;
+    /// Flag telling the wheel revolution data is present
+    pub const WHEEL_REVOLUTION_DATA: u8 = 1 << 0;
+    /// Flag telling the crank revolution data is present
+    pub const CRANK_REVOLUTION_DATA: u8 = 1 << 1;
+
+    pub const fn new() -> Self {
+        Self {
+            wheel_revolutions: 0,
+            last_wheel_event: 0,
+        }
+    }
+
+    /// Count a revolution that happened `at_us` microseconds since boot
+    pub fn add_revolution(&mut self, at_us: u64) {
+        self.wheel_revolutions = self.wheel_revolutions.wrapping_add(1);
+        self.last_wheel_event = event_time(at_us);
+    }
+
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let buf = buf
+            .get_mut(..Self::LEN)
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+
+        buf[0] = Self::WHEEL_REVOLUTION_DATA;
+        buf[1..5].copy_from_slice(&self.wheel_revolutions.to_le_bytes());
+        buf[5..7].copy_from_slice(&self.last_wheel_event.to_le_bytes());
+
+        Ok(buf.len())
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != Self::LEN {
+            return Err(Error::InvalidLength);
+        }
+        if data[0] != Self::WHEEL_REVOLUTION_DATA {
+            return Err(Error::InvalidValue);
+        }
+
+        Ok(Self {
+            wheel_revolutions: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+            last_wheel_event: u16::from_le_bytes([data[5], data[6]]),
+        })
+    }
+}
+
+/// Microseconds to the 1/1024 s resolution of CSC event times
+fn event_time(at_us: u64) -> u16 {
+    (at_us * 1024 / 1_000_000) as u16
+}
@@ -0,0 +1,206 @@
+//! Frames on the UART link are COBS encoded and terminated by a delimiter byte. The
+//! encoded data never contains the delimiter, so after a corrupted or dropped byte the
+//! receiver only loses the current frame and picks up again at the next one.
+//!
+//! Before encoding, a frame starts with a 2 byte header followed by the command data. The
+//! exact layout depends on the [FrameFormat] that was picked during the handshake.
+
+pub const DELIMITER: u8 = 0;
+
+/// Layout of a frame before it is encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// `[cmd, crc8, data...]` where the checksum only covers the data. Used when the host
+    /// doesn't negotiate [FrameFormat::Crc16].
+    Crc8,
+    /// `[cmd, len, data..., crc16]` where the big endian CRC-16/CCITT covers the header
+    /// and the data
+    Crc16,
+}
+
+impl FrameFormat {
+    /// Offset of the command data in a frame, the same for all formats
+    pub const DATA_OFFSET: usize = 2;
+    /// Max amount of bytes a frame has after the command data
+    pub const MAX_TRAILER_LEN: usize = 2;
+    /// Max amount of bytes a frame adds around the command data
+    pub const MAX_OVERHEAD: usize = Self::DATA_OFFSET + Self::MAX_TRAILER_LEN;
+
+    /// Fill in the header and checksum of a frame in `buf`, the command data must already
+    /// be written at [FrameFormat::DATA_OFFSET]. Returns the total frame length.
+    pub fn finish(self, buf: &mut [u8], cmd: u8, data_len: usize) -> usize {
+        let data_end = Self::DATA_OFFSET + data_len;
+        buf[0] = cmd;
+
+        match self {
+            Self::Crc8 => {
+                buf[1] = calc_crc8(&buf[Self::DATA_OFFSET..data_end]);
+
+                data_end
+            }
+            Self::Crc16 => {
+                buf[1] = data_len as u8;
+                let crc = calc_crc16(&buf[..data_end]);
+                buf[data_end..data_end + 2].copy_from_slice(&crc.to_be_bytes());
+
+                data_end + 2
+            }
+        }
+    }
+
+    /// Verify a decoded frame, returns the command id and the command data
+    pub fn parse(self, raw: &[u8]) -> Option<(u8, &[u8])> {
+        if raw.len() < Self::DATA_OFFSET {
+            return None;
+        }
+
+        match self {
+            Self::Crc8 => {
+                let data = &raw[Self::DATA_OFFSET..];
+
+                if raw[1] == calc_crc8(data) {
+                    Some((raw[0], data))
+                } else {
+                    None
+                }
+            }
+            Self::Crc16 => {
+                let data_end = Self::DATA_OFFSET + raw[1] as usize;
+                if raw.len() != data_end + 2 {
+                    return None;
+                }
+
+                let expected = u16::from_be_bytes([raw[data_end], raw[data_end + 1]]);
+
+                if expected == calc_crc16(&raw[..data_end]) {
+                    Some((raw[0], &raw[Self::DATA_OFFSET..data_end]))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Verify a decoded frame in the given format, or in any format if it's not known yet
+pub fn parse(format: Option<FrameFormat>, raw: &[u8]) -> Option<(u8, &[u8])> {
+    match format {
+        Some(format) => format.parse(raw),
+        None => FrameFormat::Crc16
+            .parse(raw)
+            .or_else(|| FrameFormat::Crc8.parse(raw)),
+    }
+}
+
+/// Max size of `len` bytes after encoding, including the delimiter
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 2
+}
+
+/// Encode `src` into `dst` followed by the delimiter, returns the used amount of bytes
+/// or None if `dst` is too small.
+pub fn encode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    if dst.len() < max_encoded_len(src.len()) {
+        return None;
+    }
+
+    // every block starts with a code byte holding the offset to the next zero
+    let mut code_idx = 0;
+    let mut code = 1u8;
+    let mut out = 1;
+
+    for byte in src.iter().copied() {
+        if byte == 0 {
+            dst[code_idx] = code;
+            code_idx = out;
+            code = 1;
+            out += 1;
+        } else {
+            dst[out] = byte;
+            out += 1;
+            code += 1;
+
+            // max block length reached, start a new block without an implicit zero
+            if code == 0xFF {
+                dst[code_idx] = code;
+                code_idx = out;
+                code = 1;
+                out += 1;
+            }
+        }
+    }
+
+    dst[code_idx] = code;
+    dst[out] = DELIMITER;
+
+    Some(out + 1)
+}
+
+/// Decode a frame without its delimiter from `src` into `dst`, returns the decoded length
+/// or None if the frame is malformed or doesn't fit.
+pub fn decode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut idx = 0;
+    let mut out = 0;
+
+    while idx < src.len() {
+        let code = src[idx];
+        if code == DELIMITER {
+            return None;
+        }
+
+        let block_end = idx + code as usize;
+        let block = src.get(idx + 1..block_end)?;
+        idx = block_end;
+
+        for byte in block.iter().copied() {
+            if byte == DELIMITER {
+                return None;
+            }
+
+            *dst.get_mut(out)? = byte;
+            out += 1;
+        }
+
+        // each block except the last one and max length blocks ends in a zero
+        if code != 0xFF && idx < src.len() {
+            *dst.get_mut(out)? = 0;
+            out += 1;
+        }
+    }
+
+    Some(out)
+}
+
+fn calc_crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFF;
+
+    for val in data.iter().copied() {
+        crc ^= val;
+        for _ in 0..8 {
+            if (crc & 0x80) != 0 {
+                crc = (crc << 1) ^ 0x31;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    crc
+}
+
+pub fn calc_crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF;
+
+    for val in data.iter().copied() {
+        crc ^= u16::from(val) << 8;
+        for _ in 0..8 {
+            if (crc & 0x8000) != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    crc
+}
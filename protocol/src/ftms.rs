@@ -0,0 +1,107 @@
+use crate::Error;
+
+/// Without a cycle for this long the bike is considered standing still
+const STOPPED_AFTER_US: u64 = 3_000_000;
+
+/// A record in the layout of the Bluetooth Fitness Machine Service "Indoor Bike Data"
+/// characteristic, so a bridge with a GATT server can forward it verbatim. On the wire
+/// it's the little endian u16 flags followed by the fields the flags announce, all little
+/// endian: speed (u16), distance (u24) and elapsed time (u16). The magnet sensor only sees
+/// the wheel, so there is no cadence.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct IndoorBikeData {
+    /// Instantaneous speed in 0.01 km/h
+    pub speed: u16,
+    /// Total distance in meters, only the lower 24 bits are sent
+    pub distance: u32,
+    /// Seconds since the session started
+    pub elapsed_time: u16,
+}
+
+impl IndoorBikeData {
+    pub const LEN: usize = 9;
+    /// Flag telling the instantaneous speed is absent, the only inverted flag
+    pub const MORE_DATA: u16 = 1 << 0;
+    pub const TOTAL_DISTANCE: u16 = 1 << 4;
+    pub const ELAPSED_TIME: u16 = 1 << 11;
+    /// The fields this firmware sends
+    pub const FLAGS: u16 = Self::TOTAL_DISTANCE | Self::ELAPSED_TIME;
+    pub const MAX_DISTANCE: u32 = 0xFF_FFFF;
+
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let buf = buf
+            .get_mut(..Self::LEN)
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+
+        buf[0..2].copy_from_slice(&Self::FLAGS.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.speed.to_le_bytes());
+        buf[4..7].copy_from_slice(&self.distance.min(Self::MAX_DISTANCE).to_le_bytes()[..3]);
+        buf[7..9].copy_from_slice(&self.elapsed_time.to_le_bytes());
+
+        Ok(buf.len())
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != Self::LEN {
+            return Err(Error::InvalidLength);
+        }
+        if u16::from_le_bytes([data[0], data[1]]) != Self::FLAGS {
+            return Err(Error::InvalidValue);
+        }
+
+        Ok(Self {
+            speed: u16::from_le_bytes([data[2], data[3]]),
+            distance: u32::from_le_bytes([data[4], data[5], data[6], 0]),
+            elapsed_time: u16::from_le_bytes([data[7], data[8]]),
+        })
+    }
+}
+
+/// Turns the cycles of a session into [IndoorBikeData] records
+#[derive(Clone, Copy, Debug)]
+pub struct IndoorBikeTracker {
+    started_at_us: u64,
+    cycle_count: u32,
+    last_cycle_at_us: u64,
+    /// Duration of the last cycle, 0 before the first one
+    last_cycle_us: u64,
+}
+
+impl IndoorBikeTracker {
+    pub const fn new(started_at_us: u64) -> Self {
+        Self {
+            started_at_us,
+            cycle_count: 0,
+            last_cycle_at_us: started_at_us,
+            last_cycle_us: 0,
+        }
+    }
+
+    /// Count a cycle that ended at `at_us` and took `cycle_us`
+    pub fn add_cycle(&mut self, at_us: u64, cycle_us: u64) {
+        self.cycle_count = self.cycle_count.saturating_add(1);
+        self.last_cycle_at_us = at_us;
+        self.last_cycle_us = cycle_us;
+    }
+
+    /// The record at `now_us`, when every cycle covers `cycle_distance_mm`
+    pub fn record(&self, now_us: u64, cycle_distance_mm: u32) -> IndoorBikeData {
+        // a cycle that is taking longer than the last one means the rider is slowing down
+        let since_last_us = now_us.saturating_sub(self.last_cycle_at_us);
+        let period_us = self.last_cycle_us.max(since_last_us);
+
+        let speed = if self.last_cycle_us == 0 || period_us > STOPPED_AFTER_US {
+            0
+        } else {
+            u64::from(cycle_distance_mm) * 360_000 / period_us
+        };
+        let distance = u64::from(self.cycle_count) * u64::from(cycle_distance_mm) / 1000;
+        let elapsed_time = now_us.saturating_sub(self.started_at_us) / 1_000_000;
+
+        IndoorBikeData {
+            speed: speed.min(u64::from(u16::MAX)) as u16,
+            distance: distance.min(u64::from(IndoorBikeData::MAX_DISTANCE)) as u32,
+            elapsed_time: elapsed_time.min(u64::from(u16::MAX)) as u16,
+        }
+    }
+}
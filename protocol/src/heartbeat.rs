@@ -0,0 +1,54 @@
+//! Hosts that negotiated the heartbeat capability send a frame at least every heartbeat
+//! timeout, a ping when there is nothing else to send. The connection pin only tells
+//! whether the module is connected, so this is how a hung host app is noticed.
+//!
+//! A lost heartbeat doesn't end the connection, the host comes back as soon as it sends a
+//! valid frame again.
+
+/// Tracks the time since the last valid frame of the host
+pub struct Heartbeat {
+    last_frame_at_us: u64,
+    lost: bool,
+}
+
+impl Heartbeat {
+    pub const fn new() -> Self {
+        Self {
+            last_frame_at_us: 0,
+            lost: false,
+        }
+    }
+
+    /// A host connected, the timeout starts now
+    pub fn connected(&mut self, now_us: u64) {
+        self.last_frame_at_us = now_us;
+        self.lost = false;
+    }
+
+    /// A valid frame arrived, returns whether the host came back after the heartbeat was
+    /// lost
+    pub fn frame_received(&mut self, now_us: u64) -> bool {
+        self.last_frame_at_us = now_us;
+        core::mem::replace(&mut self.lost, false)
+    }
+
+    /// Returns true once when the host didn't send a frame for longer than `timeout_us`
+    pub fn poll(&mut self, now_us: u64, timeout_us: u64) -> bool {
+        if self.lost || now_us.saturating_sub(self.last_frame_at_us) <= timeout_us {
+            return false;
+        }
+
+        self.lost = true;
+        true
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
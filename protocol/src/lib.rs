@@ -0,0 +1,179 @@
+//! The wire protocol between the controller and the app, shared by the firmware and the
+//! host tools so both sides use the same definition.
+//!
+//! Commands are named from the controller's point of view: [RxCommand]s are sent by the
+//! host and received by the controller, [TxCommand]s go the other way. Both can be
+//! encoded and decoded, see [framing] for how they are put on the wire.
+
+#![no_std]
+
+#[macro_use]
+extern crate bitflags;
+
+pub mod at;
+pub mod capture;
+pub mod framing;
+pub mod heartbeat;
+pub mod retransmit;
+pub mod signing;
+pub mod tx_ring;
+pub mod update;
+
+mod batch;
+mod csc;
+mod ftms;
+mod param;
+mod parser;
+mod payload;
+mod rx;
+mod tx;
+
+pub use batch::{CycleBatch, MAX_BATCH_LEN};
+pub use csc::CscMeasurement;
+pub use ftms::{IndoorBikeData, IndoorBikeTracker};
+pub use param::Param;
+pub use parser::{RxError, RxParser};
+pub use payload::*;
+pub use rx::RxCommand;
+pub use tx::TxCommand;
+
+use serde::{Deserialize, Serialize};
+
+use core::fmt;
+
+/// Revision of the wire protocol, sent to the host in the handshake reply.
+///
+/// Version 2 introduced COBS framing for all frames, hosts that send unframed commands
+/// can't talk to this firmware anymore.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+pub const BOARD_ID_LEN: usize = 8;
+pub const SECRET_LEN: usize = 32;
+/// Uncompressed SEC1 encoding
+pub const PUBLIC_KEY_LEN: usize = 65;
+/// `r || s`
+pub const SIGNATURE_LEN: usize = 64;
+/// Length of a hash chain head, a SHA-256 digest
+pub const HEAD_LEN: usize = 32;
+/// Must be sent along with the unlock command, so it can't be unlocked by accident
+pub const UNLOCK_CODE: u32 = u32::from_le_bytes(*b"UNLK");
+/// Used in error reports that were not caused by a received command
+pub const NO_CMD: u8 = 0;
+/// Max gap between two bytes of the same frame, a partial frame is discarded after this
+pub const RX_INTERBYTE_TIMEOUT_US: u64 = 200_000;
+/// Max amount of capture bytes in a single [CaptureChunk]
+pub const CAPTURE_CHUNK_LEN: usize = 128;
+
+bitflags! {
+    /// Optional protocol features. Both sides advertise what they support during the
+    /// handshake and only the features present on both sides are used.
+    #[derive(Serialize, Deserialize)]
+    pub struct Capabilities: u32 {
+        /// A pending offline session is sent as bulk data when the host resumes a session
+        const OFFLINE_SYNC = 1 << 0;
+        /// Sent frames carry a sequence number and are sent again until the host acks them
+        const RELIABLE_DELIVERY = 1 << 1;
+        /// Frames use [framing::FrameFormat::Crc16] instead of [framing::FrameFormat::Crc8]
+        const CRC16 = 1 << 2;
+        /// Bulk data and session summaries are sent as signed records, if the device has
+        /// a key
+        const SIGNED_RECORDS = 1 << 3;
+        /// The hash chain head of a live session is sent periodically
+        const HASH_CHAIN = 1 << 4;
+        /// Live cycles are sent in batches instead of a frame per cycle
+        const LIVE_BATCHING = 1 << 5;
+        /// Failed commands and other errors are reported to the host
+        const ERROR_REPORTS = 1 << 6;
+        /// The host can ask for a higher UART baud rate
+        const BAUD_SWITCHING = 1 << 7;
+        /// The host pings regularly, the connection is considered lost when it goes quiet
+        const HEARTBEAT = 1 << 8;
+        /// Live cycles are sent as [CscMeasurement]s instead of [CycleData], live batching
+        /// is not used then
+        const CSC_MEASUREMENT = 1 << 9;
+        /// An [IndoorBikeData] record is sent every second of a live session instead of
+        /// live cycles, takes precedence over [Capabilities::CSC_MEASUREMENT]
+        const INDOOR_BIKE_DATA = 1 << 10;
+        /// The controller takes signed firmware images, see [update]
+        const FIRMWARE_UPDATE = 1 << 11;
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Postcard(postcard::Error),
+    /// The checksum or encoding of a frame is wrong
+    InvalidFrame,
+    UnknownCommand,
+    /// A command has the wrong amount of data
+    InvalidLength,
+    /// A command contains a value that is out of range
+    InvalidValue,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Postcard(err) => write!(f, "{}", err),
+            Self::InvalidFrame => write!(f, "invalid frame"),
+            Self::UnknownCommand => write!(f, "unknown command"),
+            Self::InvalidLength => write!(f, "invalid command length"),
+            Self::InvalidValue => write!(f, "invalid value in command"),
+        }
+    }
+}
+
+impl From<postcard::Error> for Error {
+    fn from(val: postcard::Error) -> Self {
+        Self::Postcard(val)
+    }
+}
+
+/// Stable codes sent in [ErrorReport]s
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    Serialization = 1,
+    NotStarted = 2,
+    NoConnection = 3,
+    BufferFull = 4,
+    InvalidFrame = 5,
+    UnknownCommand = 6,
+    InvalidLength = 7,
+    InvalidValue = 8,
+    ProvisioningLocked = 9,
+    InvalidKey = 10,
+    /// The bluetooth module didn't accept an AT command
+    ModuleError = 11,
+    /// The CRC of a firmware update chunk is wrong
+    InvalidChecksum = 12,
+    /// The firmware image doesn't match its announced SHA-256
+    InvalidImage = 13,
+    /// The firmware image is not signed with the update key
+    InvalidSignature = 14,
+    /// The flash doesn't hold what was written to it
+    FlashError = 15,
+}
+
+impl ErrorCode {
+    /// None for codes of newer firmware versions
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::Serialization),
+            2 => Some(Self::NotStarted),
+            3 => Some(Self::NoConnection),
+            4 => Some(Self::BufferFull),
+            5 => Some(Self::InvalidFrame),
+            6 => Some(Self::UnknownCommand),
+            7 => Some(Self::InvalidLength),
+            8 => Some(Self::InvalidValue),
+            9 => Some(Self::ProvisioningLocked),
+            10 => Some(Self::InvalidKey),
+            11 => Some(Self::ModuleError),
+            12 => Some(Self::InvalidChecksum),
+            13 => Some(Self::InvalidImage),
+            14 => Some(Self::InvalidSignature),
+            15 => Some(Self::FlashError),
+            _ => None,
+        }
+    }
+}
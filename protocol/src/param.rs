@@ -0,0 +1,53 @@
+/// Tuning values the host can change at runtime. The ids are part of the protocol, new
+/// params are added at the end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    /// Shortest time between two magnet pulses that is counted as a cycle, shorter
+    /// pulses are considered bounces
+    MinCycleDeltaUs,
+    /// Time the host gets to come back after the connection is lost
+    ReconnectTimeoutUs,
+    /// Gap between two sent frames, so the bluetooth module doesn't merge them
+    FramePacingUs,
+    /// Max PWM level of a led channel
+    MaxBrightness,
+    ConnectedHue,
+    StartedHue,
+    ReconnectingHue,
+    OfflineModeHue,
+    /// The connection is considered lost when a host that supports heartbeats doesn't
+    /// send anything for this long
+    HeartbeatTimeoutUs,
+    /// 1 to record the traffic on the UART link, setting it starts a new capture. Reading
+    /// the capture sets it back to 0.
+    Capture,
+    /// Distance the bike travels per counted cycle, for the speed and distance of
+    /// indoor bike data
+    CycleDistanceMm,
+}
+
+impl Param {
+    pub const COUNT: usize = 11;
+
+    /// The id used for this param on the wire
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::MinCycleDeltaUs),
+            1 => Some(Self::ReconnectTimeoutUs),
+            2 => Some(Self::FramePacingUs),
+            3 => Some(Self::MaxBrightness),
+            4 => Some(Self::ConnectedHue),
+            5 => Some(Self::StartedHue),
+            6 => Some(Self::ReconnectingHue),
+            7 => Some(Self::OfflineModeHue),
+            8 => Some(Self::HeartbeatTimeoutUs),
+            9 => Some(Self::Capture),
+            10 => Some(Self::CycleDistanceMm),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{framing, Error, RxCommand};
+
+/// A received frame that could not be turned into a command
+#[derive(Debug)]
+pub struct RxError {
+    /// Id of the command the frame seems to hold, not trustworthy for invalid frames but it
+    /// might still help
+    pub cmd: u8,
+    pub error: Error,
+}
+
+/// Turns the bytes received from the host into commands, one byte at a time. Invalid input
+/// only costs the frame it's part of: the parser picks up again after the next delimiter,
+/// or after a gap of `interbyte_timeout_us` in the middle of a frame.
+pub struct RxParser {
+    buf: [u8; RxCommand::MAX_ENCODED_LEN],
+    len: usize,
+    /// Set when the frame being received can't fit in the buffer, the rest of it is dropped
+    /// until the next delimiter
+    discarding: bool,
+    last_byte_us: u64,
+    interbyte_timeout_us: u64,
+    stale_frames: u32,
+}
+
+impl RxParser {
+    pub const fn new(interbyte_timeout_us: u64) -> Self {
+        Self {
+            buf: [0; RxCommand::MAX_ENCODED_LEN],
+            len: 0,
+            discarding: false,
+            last_byte_us: 0,
+            interbyte_timeout_us,
+            stale_frames: 0,
+        }
+    }
+
+    /// Partially received frames that were discarded because the rest never came
+    pub fn stale_frames(&self) -> u32 {
+        self.stale_frames
+    }
+
+    /// Feed a received byte, returns the result of parsing a frame once its delimiter
+    /// arrives. `format` is the negotiated frame format, see [RxCommand::deserialize].
+    pub fn push(
+        &mut self,
+        byte: u8,
+        now_us: u64,
+        format: Option<framing::FrameFormat>,
+    ) -> Option<Result<RxCommand, RxError>> {
+        let receiving = self.len > 0 || self.discarding;
+        if receiving && now_us.saturating_sub(self.last_byte_us) > self.interbyte_timeout_us {
+            // the host stopped in the middle of a frame, don't glue the next one onto it
+            self.reset();
+            self.stale_frames = self.stale_frames.saturating_add(1);
+        }
+        self.last_byte_us = now_us;
+
+        if byte != framing::DELIMITER {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                // too long to be a valid frame
+                None => self.discarding = true,
+            }
+
+            return None;
+        }
+
+        let result = if !self.discarding && self.len > 0 {
+            Some(self.parse_frame(format))
+        } else {
+            None
+        };
+
+        // ready to receive a new frame
+        self.reset();
+
+        result
+    }
+
+    fn parse_frame(&self, format: Option<framing::FrameFormat>) -> Result<RxCommand, RxError> {
+        let mut frame = [0u8; RxCommand::BUF_SIZE];
+
+        framing::decode(&self.buf[..self.len], &mut frame)
+            .ok_or(Error::InvalidFrame)
+            .and_then(|len| RxCommand::deserialize(&frame[..len], format))
+            .map_err(|error| RxError {
+                cmd: frame[0],
+                error,
+            })
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.discarding = false;
+    }
+}
@@ -0,0 +1,327 @@
+//! Data carried by the commands. Unless noted otherwise they are postcard encoded.
+
+use crate::{
+    Capabilities, Error, TxCommand, BOARD_ID_LEN, CAPTURE_CHUNK_LEN, HEAD_LEN, PUBLIC_KEY_LEN,
+    SIGNATURE_LEN,
+};
+
+use serde::{Deserialize, Serialize};
+
+use core::convert::TryInto;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CycleData {
+    pub millis: u32,
+}
+
+/// Totals of a session recorded while there was no connection
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BulkCycleData {
+    pub millis: u32,
+    pub cycle_count: u16,
+    /// Wall-clock time the offline session started, 0 if the clock was not set
+    pub started_at: u64,
+    // session_flags: SessionFlags,
+}
+
+impl BulkCycleData {
+    pub const fn new(started_at: u64) -> Self {
+        Self {
+            millis: 0,
+            cycle_count: 0,
+            started_at,
+            // session_flags: SessionFlags::empty(),
+        }
+    }
+
+    /// Returns false if the totals can't hold another cycle
+    pub fn add_cycle(&mut self, data: &CycleData) -> bool {
+        let millis_result = self.millis.overflowing_add(data.millis);
+
+        if self.cycle_count == u16::MAX || millis_result.1 {
+            return false;
+        }
+
+        self.cycle_count += 1;
+        self.millis = millis_result.0;
+
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HandshakeReply {
+    pub protocol_version: u8,
+    /// Everything the controller supports, not only what the host asked for
+    pub capabilities: Capabilities,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkStats {
+    pub retransmits: u32,
+    /// Partially received frames that were discarded because the rest never came
+    pub stale_rx_frames: u32,
+}
+
+/// Sent when the host stops a live session
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub cycle_count: u32,
+    pub millis: u32,
+    /// Milliseconds since boot
+    pub started_at_ms: u64,
+    /// Milliseconds since boot
+    pub stopped_at_ms: u64,
+    /// Wall-clock time, 0 if the clock was not set
+    pub started_at: u64,
+    /// Wall-clock time, 0 if the clock was not set
+    pub stopped_at: u64,
+    /// Hash chain head after the last cycle of the session
+    pub chain_head: [u8; HEAD_LEN],
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainCheckpoint {
+    pub cycle_count: u32,
+    pub head: [u8; HEAD_LEN],
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramState {
+    WaitForModeSelect,
+    Running { status_hue: u8 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub state: ProgramState,
+    pub started: bool,
+    /// An offline session is waiting to be sent to the host
+    pub offline_pending: bool,
+    /// Amount of commands waiting in each tx buffer
+    pub tx_buf_lens: [u8; 2],
+    pub uptime_us: u64,
+    /// Percentage, None if the battery level can't be measured
+    pub battery_level: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamValue {
+    pub id: u8,
+    pub value: u32,
+}
+
+/// Sent when the controller accepted the baud rate the host asked for. The module only
+/// takes AT commands without a connection, so the switch happens after the host
+/// disconnected.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BaudRateAck {
+    pub baud_rate: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pong {
+    /// Copied from the ping
+    pub nonce: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorReport {
+    /// One of [crate::ErrorCode], kept as a number so codes of newer firmware versions
+    /// can still be reported
+    pub code: u8,
+    /// Id of the received command that failed, or [crate::NO_CMD]
+    pub cmd: u8,
+}
+
+/// The public key is too large for serde, so this is encoded by hand: the board id
+/// followed by the public key, which is left out when not provisioned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub board_id: [u8; BOARD_ID_LEN],
+    /// None if the device is not provisioned yet
+    pub public_key: Option<[u8; PUBLIC_KEY_LEN]>,
+}
+
+impl Identity {
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let key_len = self.public_key.map_or(0, |_| PUBLIC_KEY_LEN);
+        let buf = buf
+            .get_mut(..BOARD_ID_LEN + key_len)
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+
+        buf[..BOARD_ID_LEN].copy_from_slice(&self.board_id);
+        if let Some(public_key) = self.public_key {
+            buf[BOARD_ID_LEN..].copy_from_slice(&public_key);
+        }
+
+        Ok(buf.len())
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        let (board_id, public_key) = match data.len() {
+            BOARD_ID_LEN => (data, None),
+            len if len == BOARD_ID_LEN + PUBLIC_KEY_LEN => {
+                let (board_id, public_key) = data.split_at(BOARD_ID_LEN);
+                (board_id, Some(public_key.try_into().unwrap()))
+            }
+            _ => return Err(Error::InvalidLength),
+        };
+
+        Ok(Self {
+            board_id: board_id.try_into().unwrap(),
+            public_key,
+        })
+    }
+}
+
+/// Data that can be sent as a signed record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Record {
+    BulkData(BulkCycleData),
+    SessionSummary(SessionSummary),
+}
+
+impl Record {
+    /// The id of the cmd used to send the record unsigned
+    pub fn kind(&self) -> u8 {
+        match self {
+            Self::BulkData(_) => TxCommand::CMD_BULK_DATA,
+            Self::SessionSummary(_) => TxCommand::CMD_SESSION_SUMMARY,
+        }
+    }
+
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let used = match self {
+            Self::BulkData(data) => postcard::to_slice(data, buf)?,
+            Self::SessionSummary(data) => postcard::to_slice(data, buf)?,
+        };
+
+        Ok(used.len())
+    }
+
+    /// The record kind followed by the serialized record, this is what the signature covers
+    pub fn serialize_message(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let (buf_kind, buf_record) = buf
+            .split_first_mut()
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+        *buf_kind = self.kind();
+
+        Ok(1 + self.serialize(buf_record)?)
+    }
+
+    /// Parse a message made by [Record::serialize_message], returns the record and the
+    /// data after it
+    pub fn take_message(data: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (kind, rest) = data.split_first().ok_or(Error::InvalidLength)?;
+
+        match *kind {
+            TxCommand::CMD_BULK_DATA => {
+                let (record, rest) = postcard::take_from_bytes(rest)?;
+                Ok((Self::BulkData(record), rest))
+            }
+            TxCommand::CMD_SESSION_SUMMARY => {
+                let (record, rest) = postcard::take_from_bytes(rest)?;
+                Ok((Self::SessionSummary(record), rest))
+            }
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// The record message followed by the signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignedRecord {
+    pub record: Record,
+    /// Signature over [Record::serialize_message]
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl SignedRecord {
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let message_len = self.record.serialize_message(buf)?;
+        let buf_signature = buf
+            .get_mut(message_len..message_len + SIGNATURE_LEN)
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+        buf_signature.copy_from_slice(&self.signature);
+
+        Ok(message_len + SIGNATURE_LEN)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        let (record, rest) = Record::take_message(data)?;
+        let signature = rest.try_into().map_err(|_| Error::InvalidLength)?;
+
+        Ok(Self { record, signature })
+    }
+}
+
+/// Part of the capture, in the [crate::capture] file format. Encoded by hand as the little
+/// endian offset and total length, followed by the data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureChunk {
+    /// Position of the data in the capture
+    pub offset: u16,
+    /// Size of the whole capture
+    pub total_len: u16,
+    data: [u8; CAPTURE_CHUNK_LEN],
+    len: u8,
+}
+
+impl CaptureChunk {
+    /// Fill a chunk through `read`, which gets the buffer and returns the used length
+    pub fn new(offset: u16, total_len: u16, read: impl FnOnce(&mut [u8]) -> usize) -> Self {
+        let mut data = [0u8; CAPTURE_CHUNK_LEN];
+        let len = read(&mut data).min(CAPTURE_CHUNK_LEN);
+
+        Self {
+            offset,
+            total_len,
+            data,
+            len: len as u8,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+
+    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, postcard::Error> {
+        let data = self.data();
+        let buf = buf
+            .get_mut(..4 + data.len())
+            .ok_or(postcard::Error::SerializeBufferFull)?;
+
+        buf[0..2].copy_from_slice(&self.offset.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.total_len.to_le_bytes());
+        buf[4..].copy_from_slice(data);
+
+        Ok(buf.len())
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < 4 || data.len() > 4 + CAPTURE_CHUNK_LEN {
+            return Err(Error::InvalidLength);
+        }
+
+        let (header, data) = data.split_at(4);
+
+        Ok(Self::new(
+            u16::from_le_bytes([header[0], header[1]]),
+            u16::from_le_bytes([header[2], header[3]]),
+            |buf| {
+                buf[..data.len()].copy_from_slice(data);
+                data.len()
+            },
+        ))
+    }
+}
+
+/// Progress of a firmware update, see [crate::update]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpdateStatus {
+    /// Amount of image bytes stored so far, the next chunk should start here
+    pub received: u32,
+    /// A verified image is waiting for the bootloader
+    pub staged: bool,
+}
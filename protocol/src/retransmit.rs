@@ -0,0 +1,90 @@
+//! The controller side of [Capabilities::RELIABLE_DELIVERY]: frames carry a sequence
+//! number and are sent again until the host acknowledges them.
+//!
+//! [Capabilities::RELIABLE_DELIVERY]: crate::Capabilities::RELIABLE_DELIVERY
+
+use arrayvec::ArrayVec;
+
+/// Keeps sent frames around until the host acknowledges them, so they can be sent
+/// again when an acknowledgement doesn't arrive in time.
+pub struct RetransmitWindow<T, const N: usize> {
+    frames: ArrayVec<PendingFrame<T>, N>,
+    next_seq: u8,
+    retransmits: u32,
+}
+
+struct PendingFrame<T> {
+    seq: u8,
+    sent_at_us: u64,
+    item: T,
+}
+
+impl<T: Copy, const N: usize> RetransmitWindow<T, N> {
+    pub fn new() -> Self {
+        Self {
+            frames: ArrayVec::new(),
+            next_seq: 0,
+            retransmits: 0,
+        }
+    }
+
+    /// Store an item that is about to be sent, returning the sequence number it should
+    /// be sent with. Returns None if the window is full.
+    pub fn push(&mut self, item: T, now_us: u64) -> Option<u8> {
+        let seq = self.next_seq;
+
+        self.frames
+            .try_push(PendingFrame {
+                seq,
+                sent_at_us: now_us,
+                item,
+            })
+            .ok()?;
+        self.next_seq = seq.wrapping_add(1);
+
+        Some(seq)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frames.is_full()
+    }
+
+    /// Acknowledge all frames up to and including `seq`
+    pub fn ack(&mut self, seq: u8) {
+        // sequence numbers wrap around, anything in the half before `seq` is considered older.
+        // the window is much smaller than half the sequence space so this can't be ambiguous
+        self.frames
+            .retain(|frame| seq.wrapping_sub(frame.seq) >= 0x80);
+    }
+
+    /// Get the oldest frame that was not acknowledged within `timeout_us`, the frame is
+    /// considered resent at `now_us`.
+    pub fn next_expired(&mut self, now_us: u64, timeout_us: u64) -> Option<(u8, T)> {
+        let frame = self
+            .frames
+            .iter_mut()
+            .find(|frame| now_us.saturating_sub(frame.sent_at_us) >= timeout_us)?;
+
+        frame.sent_at_us = now_us;
+        self.retransmits = self.retransmits.saturating_add(1);
+
+        Some((frame.seq, frame.item))
+    }
+
+    /// Total amount of frames that had to be sent again
+    pub fn retransmits(&self) -> u32 {
+        self.retransmits
+    }
+
+    /// Drop all frames and start a new sequence at 0, like the host expects after a handshake
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.next_seq = 0;
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RetransmitWindow<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
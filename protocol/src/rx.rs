@@ -0,0 +1,347 @@
+use crate::{
+    framing::{self, FrameFormat},
+    update::{DIGEST_LEN, UPDATE_CHUNK_LEN},
+    Capabilities, Error, Param, PROTOCOL_VERSION, SECRET_LEN, SIGNATURE_LEN,
+};
+
+use core::mem;
+
+/// Commands sent by the host to the controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxCommand {
+    StartSession,
+    StopSession,
+    Handshake {
+        session_active: bool,
+        protocol_version: u8,
+        capabilities: Capabilities,
+    },
+    /// The host received all frames up to and including `seq`
+    Ack {
+        seq: u8,
+    },
+    GetLinkStats,
+    GenerateKey,
+    ImportKey {
+        secret: [u8; SECRET_LEN],
+    },
+    /// Allow replacing the key of a provisioned device once
+    UnlockProvisioning {
+        code: u32,
+    },
+    GetIdentity,
+    /// Set the RTC, the time is packed the way the firmware's `datetime_t::from_bits`
+    /// expects
+    SetTime {
+        bits: u64,
+    },
+    GetStatus,
+    GetParam {
+        param: Param,
+    },
+    /// Change a tuning value, takes effect immediately
+    SetParam {
+        param: Param,
+        value: u32,
+    },
+    /// Switch the link between the controller and the bluetooth module to another
+    /// baud rate, from the next connection on
+    SetBaudRate {
+        baud_rate: u32,
+    },
+    Ping {
+        nonce: u32,
+    },
+    /// Read the traffic capture starting at `offset`, this stops recording
+    ReadCapture {
+        offset: u16,
+    },
+    /// Start a firmware update, see [crate::update]
+    BeginUpdate {
+        image_len: u32,
+        /// SHA-256 of the image
+        digest: [u8; DIGEST_LEN],
+    },
+    UpdateChunk {
+        /// Position of the data in the image
+        offset: u32,
+        /// CRC-16 of `data`
+        crc: u16,
+        data: [u8; UPDATE_CHUNK_LEN],
+    },
+    /// Verify the received image and stage it for the bootloader
+    FinishUpdate {
+        signature: [u8; SIGNATURE_LEN],
+    },
+}
+
+impl RxCommand {
+    pub const BUF_SIZE: usize = FrameFormat::MAX_OVERHEAD + mem::size_of::<Self>();
+    pub const MAX_ENCODED_LEN: usize = framing::max_encoded_len(Self::BUF_SIZE);
+    pub const CMD_START_SESSION: u8 = 1;
+    pub const CMD_STOP_SESSION: u8 = 2;
+    // 3 was the short handshake of unframed hosts
+    pub const CMD_HANDSHAKE: u8 = 4;
+    pub const CMD_ACK: u8 = 5;
+    pub const CMD_GET_LINK_STATS: u8 = 6;
+    pub const CMD_GENERATE_KEY: u8 = 7;
+    pub const CMD_IMPORT_KEY: u8 = 8;
+    pub const CMD_UNLOCK_PROVISIONING: u8 = 9;
+    pub const CMD_GET_IDENTITY: u8 = 10;
+    pub const CMD_SET_TIME: u8 = 11;
+    pub const CMD_GET_STATUS: u8 = 12;
+    pub const CMD_GET_PARAM: u8 = 13;
+    pub const CMD_SET_PARAM: u8 = 14;
+    pub const CMD_SET_BAUD_RATE: u8 = 15;
+    pub const CMD_PING: u8 = 16;
+    pub const CMD_READ_CAPTURE: u8 = 17;
+    pub const CMD_BEGIN_UPDATE: u8 = 18;
+    pub const CMD_UPDATE_CHUNK: u8 = 19;
+    pub const CMD_FINISH_UPDATE: u8 = 20;
+
+    fn expected_len(raw: u8) -> Option<usize> {
+        match raw {
+            Self::CMD_START_SESSION => Some(0),
+            Self::CMD_STOP_SESSION => Some(0),
+            // flags, protocol version and a little endian capability bitmap
+            Self::CMD_HANDSHAKE => Some(6),
+            Self::CMD_ACK => Some(1),
+            Self::CMD_GET_LINK_STATS => Some(0),
+            Self::CMD_GENERATE_KEY => Some(0),
+            Self::CMD_IMPORT_KEY => Some(SECRET_LEN),
+            Self::CMD_UNLOCK_PROVISIONING => Some(4),
+            Self::CMD_GET_IDENTITY => Some(0),
+            Self::CMD_SET_TIME => Some(8),
+            Self::CMD_GET_STATUS => Some(0),
+            Self::CMD_GET_PARAM => Some(1),
+            // param id and a little endian value
+            Self::CMD_SET_PARAM => Some(5),
+            Self::CMD_SET_BAUD_RATE => Some(4),
+            Self::CMD_PING => Some(4),
+            Self::CMD_READ_CAPTURE => Some(2),
+            // little endian image length and the digest
+            Self::CMD_BEGIN_UPDATE => Some(4 + DIGEST_LEN),
+            // little endian offset and crc, followed by the data
+            Self::CMD_UPDATE_CHUNK => Some(6 + UPDATE_CHUNK_LEN),
+            Self::CMD_FINISH_UPDATE => Some(SIGNATURE_LEN),
+            _ => None,
+        }
+    }
+
+    /// The handshake a host of this protocol version sends
+    pub fn handshake(session_active: bool, capabilities: Capabilities) -> Self {
+        Self::Handshake {
+            session_active,
+            protocol_version: PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+
+    pub fn cmd(&self) -> u8 {
+        match self {
+            Self::StartSession => Self::CMD_START_SESSION,
+            Self::StopSession => Self::CMD_STOP_SESSION,
+            Self::Handshake { .. } => Self::CMD_HANDSHAKE,
+            Self::Ack { .. } => Self::CMD_ACK,
+            Self::GetLinkStats => Self::CMD_GET_LINK_STATS,
+            Self::GenerateKey => Self::CMD_GENERATE_KEY,
+            Self::ImportKey { .. } => Self::CMD_IMPORT_KEY,
+            Self::UnlockProvisioning { .. } => Self::CMD_UNLOCK_PROVISIONING,
+            Self::GetIdentity => Self::CMD_GET_IDENTITY,
+            Self::SetTime { .. } => Self::CMD_SET_TIME,
+            Self::GetStatus => Self::CMD_GET_STATUS,
+            Self::GetParam { .. } => Self::CMD_GET_PARAM,
+            Self::SetParam { .. } => Self::CMD_SET_PARAM,
+            Self::SetBaudRate { .. } => Self::CMD_SET_BAUD_RATE,
+            Self::Ping { .. } => Self::CMD_PING,
+            Self::ReadCapture { .. } => Self::CMD_READ_CAPTURE,
+            Self::BeginUpdate { .. } => Self::CMD_BEGIN_UPDATE,
+            Self::UpdateChunk { .. } => Self::CMD_UPDATE_CHUNK,
+            Self::FinishUpdate { .. } => Self::CMD_FINISH_UPDATE,
+        }
+    }
+
+    /// Write the unencoded frame to `buf`, returns the frame length
+    pub fn serialize(&self, format: FrameFormat, buf: &mut [u8; Self::BUF_SIZE]) -> usize {
+        let data = &mut buf[FrameFormat::DATA_OFFSET..];
+
+        let data_len = match *self {
+            Self::StartSession
+            | Self::StopSession
+            | Self::GetLinkStats
+            | Self::GenerateKey
+            | Self::GetIdentity
+            | Self::GetStatus => 0,
+            Self::Handshake {
+                session_active,
+                protocol_version,
+                capabilities,
+            } => {
+                data[0] = session_active as u8;
+                data[1] = protocol_version;
+                data[2..6].copy_from_slice(&capabilities.bits().to_le_bytes());
+                6
+            }
+            Self::Ack { seq } => {
+                data[0] = seq;
+                1
+            }
+            Self::ImportKey { secret } => {
+                data[..SECRET_LEN].copy_from_slice(&secret);
+                SECRET_LEN
+            }
+            Self::UnlockProvisioning { code } => {
+                data[..4].copy_from_slice(&code.to_le_bytes());
+                4
+            }
+            Self::SetTime { bits } => {
+                data[..8].copy_from_slice(&bits.to_le_bytes());
+                8
+            }
+            Self::GetParam { param } => {
+                data[0] = param.id();
+                1
+            }
+            Self::SetParam { param, value } => {
+                data[0] = param.id();
+                data[1..5].copy_from_slice(&value.to_le_bytes());
+                5
+            }
+            Self::SetBaudRate { baud_rate } => {
+                data[..4].copy_from_slice(&baud_rate.to_le_bytes());
+                4
+            }
+            Self::Ping { nonce } => {
+                data[..4].copy_from_slice(&nonce.to_le_bytes());
+                4
+            }
+            Self::ReadCapture { offset } => {
+                data[..2].copy_from_slice(&offset.to_le_bytes());
+                2
+            }
+            Self::BeginUpdate { image_len, digest } => {
+                data[..4].copy_from_slice(&image_len.to_le_bytes());
+                data[4..4 + DIGEST_LEN].copy_from_slice(&digest);
+                4 + DIGEST_LEN
+            }
+            Self::UpdateChunk {
+                offset,
+                crc,
+                data: chunk,
+            } => {
+                data[..4].copy_from_slice(&offset.to_le_bytes());
+                data[4..6].copy_from_slice(&crc.to_le_bytes());
+                data[6..6 + UPDATE_CHUNK_LEN].copy_from_slice(&chunk);
+                6 + UPDATE_CHUNK_LEN
+            }
+            Self::FinishUpdate { signature } => {
+                data[..SIGNATURE_LEN].copy_from_slice(&signature);
+                SIGNATURE_LEN
+            }
+        };
+
+        format.finish(buf, self.cmd(), data_len)
+    }
+
+    /// `format` is the negotiated frame format, before the handshake any format is accepted
+    pub fn deserialize(raw: &[u8], format: Option<FrameFormat>) -> Result<Self, Error> {
+        let (cmd, data) = framing::parse(format, raw).ok_or(Error::InvalidFrame)?;
+
+        Self::parse(cmd, data)
+    }
+
+    /// Parse the data of a command
+    pub fn parse(cmd: u8, data: &[u8]) -> Result<Self, Error> {
+        if let Some(expected_len) = Self::expected_len(cmd) {
+            if data.len() != expected_len {
+                return Err(Error::InvalidLength);
+            }
+
+            match cmd {
+                Self::CMD_START_SESSION => Ok(Self::StartSession),
+                Self::CMD_STOP_SESSION => Ok(Self::StopSession),
+                Self::CMD_HANDSHAKE => {
+                    let flags = data[0];
+
+                    let session_active = (flags & (1 << 0)) != 0;
+                    let protocol_version = data[1];
+                    let bits = u32::from_le_bytes([data[2], data[3], data[4], data[5]]);
+
+                    Ok(Self::Handshake {
+                        session_active,
+                        protocol_version,
+                        // capabilities introduced by newer hosts are unknown to us, drop them
+                        capabilities: Capabilities::from_bits_truncate(bits),
+                    })
+                }
+                Self::CMD_ACK => Ok(Self::Ack { seq: data[0] }),
+                Self::CMD_GET_LINK_STATS => Ok(Self::GetLinkStats),
+                Self::CMD_GENERATE_KEY => Ok(Self::GenerateKey),
+                Self::CMD_IMPORT_KEY => {
+                    let mut secret = [0u8; SECRET_LEN];
+                    secret.copy_from_slice(data);
+
+                    Ok(Self::ImportKey { secret })
+                }
+                Self::CMD_UNLOCK_PROVISIONING => Ok(Self::UnlockProvisioning {
+                    code: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                }),
+                Self::CMD_GET_IDENTITY => Ok(Self::GetIdentity),
+                Self::CMD_SET_TIME => {
+                    let mut bits = [0u8; 8];
+                    bits.copy_from_slice(data);
+
+                    Ok(Self::SetTime {
+                        bits: u64::from_le_bytes(bits),
+                    })
+                }
+                Self::CMD_GET_STATUS => Ok(Self::GetStatus),
+                Self::CMD_GET_PARAM => Ok(Self::GetParam {
+                    param: Param::from_id(data[0]).ok_or(Error::InvalidValue)?,
+                }),
+                Self::CMD_SET_PARAM => Ok(Self::SetParam {
+                    param: Param::from_id(data[0]).ok_or(Error::InvalidValue)?,
+                    value: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+                }),
+                Self::CMD_SET_BAUD_RATE => Ok(Self::SetBaudRate {
+                    baud_rate: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                }),
+                Self::CMD_PING => Ok(Self::Ping {
+                    nonce: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                }),
+                Self::CMD_READ_CAPTURE => Ok(Self::ReadCapture {
+                    offset: u16::from_le_bytes([data[0], data[1]]),
+                }),
+                Self::CMD_BEGIN_UPDATE => {
+                    let mut digest = [0u8; DIGEST_LEN];
+                    digest.copy_from_slice(&data[4..]);
+
+                    Ok(Self::BeginUpdate {
+                        image_len: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                        digest,
+                    })
+                }
+                Self::CMD_UPDATE_CHUNK => {
+                    let mut chunk = [0u8; UPDATE_CHUNK_LEN];
+                    chunk.copy_from_slice(&data[6..]);
+
+                    Ok(Self::UpdateChunk {
+                        offset: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                        crc: u16::from_le_bytes([data[4], data[5]]),
+                        data: chunk,
+                    })
+                }
+                Self::CMD_FINISH_UPDATE => {
+                    let mut signature = [0u8; SIGNATURE_LEN];
+                    signature.copy_from_slice(data);
+
+                    Ok(Self::FinishUpdate { signature })
+                }
+                // we got an expected len so the cmd should be valid
+                _ => unreachable!(),
+            }
+        } else {
+            Err(Error::UnknownCommand)
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Records the backend needs to trust, like ride totals, are signed with a per-device
+//! P-256 key. The signed message is [Record::serialize_message], the record kind byte
+//! followed by the postcard encoded record, hashed with SHA-256. Signatures are sent as the
+//! 64 byte `r || s` encoding, so they can be verified on any host with a regular ECDSA
+//! P-256/SHA-256 implementation.
+
+use crate::{Record, SignedRecord, TxCommand, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+
+use core::convert::TryFrom;
+use p256::ecdsa::{
+    signature::{DigestSigner, DigestVerifier},
+    Signature, SigningKey, VerifyingKey,
+};
+use sha2::{Digest, Sha256};
+
+pub fn sign(key: &SigningKey, record: Record) -> SignedRecord {
+    let signature: Signature = key.sign_digest(message_digest(&record));
+
+    let mut raw = [0u8; SIGNATURE_LEN];
+    raw.copy_from_slice(signature.as_ref());
+
+    SignedRecord {
+        record,
+        signature: raw,
+    }
+}
+
+/// Check the signature of a record against the uncompressed SEC1 public key of a device
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], signed: &SignedRecord) -> bool {
+    let key = match VerifyingKey::from_sec1_bytes(public_key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match Signature::try_from(&signed.signature[..]) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    key.verify_digest(message_digest(&signed.record), &signature)
+        .is_ok()
+}
+
+fn message_digest(record: &Record) -> Sha256 {
+    let mut buf = [0u8; TxCommand::MAX_FRAME_LEN];
+    // a record always fits in a frame
+    let message_len = record.serialize_message(&mut buf).unwrap();
+
+    Sha256::new().chain(&buf[..message_len])
+}
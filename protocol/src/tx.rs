@@ -0,0 +1,185 @@
+use crate::{
+    framing::{self, FrameFormat},
+    BaudRateAck, BulkCycleData, CaptureChunk, ChainCheckpoint, CscMeasurement, CycleBatch,
+    CycleData, Error, ErrorReport, HandshakeReply, Identity, IndoorBikeData, LinkStats, ParamValue,
+    Pong, Record, SessionSummary, SignedRecord, Status, UpdateStatus,
+};
+
+use core::mem;
+
+/// Commands sent by the controller to the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxCommand {
+    LiveData(CycleData),
+    BulkData(BulkCycleData),
+    Handshake(HandshakeReply),
+    LinkStats(LinkStats),
+    SessionSummary(SessionSummary),
+    SignedRecord(SignedRecord),
+    ChainCheckpoint(ChainCheckpoint),
+    Identity(Identity),
+    LiveBatch(CycleBatch),
+    Error(ErrorReport),
+    Status(Status),
+    Param(ParamValue),
+    BaudRate(BaudRateAck),
+    Pong(Pong),
+    CaptureChunk(CaptureChunk),
+    CscMeasurement(CscMeasurement),
+    IndoorBikeData(IndoorBikeData),
+    UpdateStatus(UpdateStatus),
+}
+
+impl TxCommand {
+    /// Frame overhead, sequence number and the largest serialized command
+    pub const MAX_FRAME_LEN: usize = FrameFormat::MAX_OVERHEAD + 1 + mem::size_of::<Self>();
+    pub const CMD_LIVE_DATA: u8 = 1;
+    pub const CMD_BULK_DATA: u8 = 2;
+    pub const CMD_HANDSHAKE: u8 = 3;
+    pub const CMD_LINK_STATS: u8 = 4;
+    pub const CMD_SESSION_SUMMARY: u8 = 5;
+    pub const CMD_SIGNED_RECORD: u8 = 6;
+    pub const CMD_CHAIN_CHECKPOINT: u8 = 7;
+    pub const CMD_IDENTITY: u8 = 8;
+    pub const CMD_LIVE_BATCH: u8 = 9;
+    pub const CMD_ERROR: u8 = 10;
+    pub const CMD_STATUS: u8 = 11;
+    pub const CMD_PARAM: u8 = 12;
+    pub const CMD_BAUD_RATE: u8 = 13;
+    pub const CMD_PONG: u8 = 14;
+    pub const CMD_CAPTURE_CHUNK: u8 = 15;
+    pub const CMD_CSC_MEASUREMENT: u8 = 16;
+    pub const CMD_INDOOR_BIKE_DATA: u8 = 17;
+    pub const CMD_UPDATE_STATUS: u8 = 18;
+
+    pub fn cmd(&self) -> u8 {
+        match self {
+            Self::LiveData(_) => Self::CMD_LIVE_DATA,
+            Self::BulkData(_) => Self::CMD_BULK_DATA,
+            Self::Handshake(_) => Self::CMD_HANDSHAKE,
+            Self::LinkStats(_) => Self::CMD_LINK_STATS,
+            Self::SessionSummary(_) => Self::CMD_SESSION_SUMMARY,
+            Self::SignedRecord(_) => Self::CMD_SIGNED_RECORD,
+            Self::ChainCheckpoint(_) => Self::CMD_CHAIN_CHECKPOINT,
+            Self::Identity(_) => Self::CMD_IDENTITY,
+            Self::LiveBatch(_) => Self::CMD_LIVE_BATCH,
+            Self::Error(_) => Self::CMD_ERROR,
+            Self::Status(_) => Self::CMD_STATUS,
+            Self::Param(_) => Self::CMD_PARAM,
+            Self::BaudRate(_) => Self::CMD_BAUD_RATE,
+            Self::Pong(_) => Self::CMD_PONG,
+            Self::CaptureChunk(_) => Self::CMD_CAPTURE_CHUNK,
+            Self::CscMeasurement(_) => Self::CMD_CSC_MEASUREMENT,
+            Self::IndoorBikeData(_) => Self::CMD_INDOOR_BIKE_DATA,
+            Self::UpdateStatus(_) => Self::CMD_UPDATE_STATUS,
+        }
+    }
+
+    /// The record this command carries, for commands that can be sent as a signed record
+    pub fn record(&self) -> Option<Record> {
+        match *self {
+            Self::BulkData(data) => Some(Record::BulkData(data)),
+            Self::SessionSummary(data) => Some(Record::SessionSummary(data)),
+            _ => None,
+        }
+    }
+
+    /// When a sequence number is given it is put in front of the command data, so it is
+    /// covered by the checksum
+    pub fn serialize(
+        self,
+        format: FrameFormat,
+        seq: Option<u8>,
+        buf: &mut [u8; Self::MAX_FRAME_LEN],
+    ) -> Result<&mut [u8], Error> {
+        // leave room for the frame trailer
+        let data_end = Self::MAX_FRAME_LEN - FrameFormat::MAX_TRAILER_LEN;
+        let buf_data = &mut buf[FrameFormat::DATA_OFFSET..data_end];
+        let (buf_seq, buf_payload) = buf_data.split_at_mut(seq.map_or(0, |_| 1));
+
+        if let Some(seq) = seq {
+            buf_seq[0] = seq;
+        }
+
+        let payload_len = match self {
+            Self::LiveData(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::BulkData(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::Handshake(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::LinkStats(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::SessionSummary(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::SignedRecord(data) => data.serialize(buf_payload)?,
+            Self::ChainCheckpoint(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::Identity(data) => data.serialize(buf_payload)?,
+            Self::LiveBatch(data) => data.serialize(buf_payload)?,
+            Self::Error(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::Status(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::Param(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::BaudRate(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::Pong(data) => postcard::to_slice(&data, buf_payload)?.len(),
+            Self::CaptureChunk(data) => data.serialize(buf_payload)?,
+            Self::CscMeasurement(data) => data.serialize(buf_payload)?,
+            Self::IndoorBikeData(data) => data.serialize(buf_payload)?,
+            Self::UpdateStatus(data) => postcard::to_slice(&data, buf_payload)?.len(),
+        };
+        let data_len = buf_seq.len() + payload_len;
+
+        let frame_len = format.finish(buf, self.cmd(), data_len);
+
+        Ok(&mut buf[..frame_len])
+    }
+
+    /// Verify and parse a decoded frame, `sequenced` tells whether the command data starts
+    /// with a sequence number. Returns the sequence number and the command.
+    pub fn deserialize(
+        raw: &[u8],
+        format: Option<FrameFormat>,
+        sequenced: bool,
+    ) -> Result<(Option<u8>, Self), Error> {
+        let (cmd, data) = framing::parse(format, raw).ok_or(Error::InvalidFrame)?;
+
+        if sequenced {
+            let (seq, data) = data.split_first().ok_or(Error::InvalidLength)?;
+            Ok((Some(*seq), Self::parse(cmd, data)?))
+        } else {
+            Ok((None, Self::parse(cmd, data)?))
+        }
+    }
+
+    /// Parse the data of a command, without the sequence number
+    pub fn parse(cmd: u8, data: &[u8]) -> Result<Self, Error> {
+        let command = match cmd {
+            Self::CMD_LIVE_DATA => Self::LiveData(from_bytes(data)?),
+            Self::CMD_BULK_DATA => Self::BulkData(from_bytes(data)?),
+            Self::CMD_HANDSHAKE => Self::Handshake(from_bytes(data)?),
+            Self::CMD_LINK_STATS => Self::LinkStats(from_bytes(data)?),
+            Self::CMD_SESSION_SUMMARY => Self::SessionSummary(from_bytes(data)?),
+            Self::CMD_SIGNED_RECORD => Self::SignedRecord(SignedRecord::deserialize(data)?),
+            Self::CMD_CHAIN_CHECKPOINT => Self::ChainCheckpoint(from_bytes(data)?),
+            Self::CMD_IDENTITY => Self::Identity(Identity::deserialize(data)?),
+            Self::CMD_LIVE_BATCH => Self::LiveBatch(CycleBatch::deserialize(data)?),
+            Self::CMD_ERROR => Self::Error(from_bytes(data)?),
+            Self::CMD_STATUS => Self::Status(from_bytes(data)?),
+            Self::CMD_PARAM => Self::Param(from_bytes(data)?),
+            Self::CMD_BAUD_RATE => Self::BaudRate(from_bytes(data)?),
+            Self::CMD_PONG => Self::Pong(from_bytes(data)?),
+            Self::CMD_CAPTURE_CHUNK => Self::CaptureChunk(CaptureChunk::deserialize(data)?),
+            Self::CMD_CSC_MEASUREMENT => Self::CscMeasurement(CscMeasurement::deserialize(data)?),
+            Self::CMD_INDOOR_BIKE_DATA => Self::IndoorBikeData(IndoorBikeData::deserialize(data)?),
+            Self::CMD_UPDATE_STATUS => Self::UpdateStatus(from_bytes(data)?),
+            _ => return Err(Error::UnknownCommand),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Like [postcard::from_bytes], but trailing data is an error
+fn from_bytes<'a, T: serde::Deserialize<'a>>(data: &'a [u8]) -> Result<T, Error> {
+    let (value, rest) = postcard::take_from_bytes(data)?;
+
+    if rest.is_empty() {
+        Ok(value)
+    } else {
+        Err(Error::InvalidLength)
+    }
+}
@@ -0,0 +1,62 @@
+/// Byte ring buffer holding encoded frames until the UART TX interrupt sends them
+pub struct TxRing<const N: usize> {
+    buf: [u8; N],
+    /// Index of the next byte to send
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> TxRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append a complete frame, returns false without writing anything if it doesn't fit
+    pub fn push_frame(&mut self, frame: &[u8]) -> bool {
+        if frame.len() > self.free() {
+            return false;
+        }
+
+        for byte in frame.iter().copied() {
+            self.buf[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let byte = self.buf[self.start];
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+
+        Some(byte)
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for TxRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,219 @@
+//! Firmware updates over the host link. The host announces the image and its SHA-256 with
+//! [RxCommand::BeginUpdate], streams it in [RxCommand::UpdateChunk]s and sends the
+//! signature with [RxCommand::FinishUpdate]. Every step is answered with an
+//! [UpdateStatus], the host continues from [UpdateStatus::received] so lost or repeated
+//! chunks don't need any other recovery.
+//!
+//! Chunks always carry [UPDATE_CHUNK_LEN] bytes, the part past the end of the image is
+//! ignored. The CRC-16 of a chunk covers all of its data. The signature is the 64 byte
+//! `r || s` ECDSA P-256 signature over the SHA-256 of the image, the image is only handed
+//! to the bootloader when it matches the key built into the firmware.
+//!
+//! [RxCommand::BeginUpdate]: crate::RxCommand::BeginUpdate
+//! [RxCommand::UpdateChunk]: crate::RxCommand::UpdateChunk
+//! [RxCommand::FinishUpdate]: crate::RxCommand::FinishUpdate
+
+use crate::{framing, UpdateStatus, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+
+use core::convert::TryFrom;
+use p256::ecdsa::{signature::DigestVerifier, Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// Image bytes in a single chunk
+pub const UPDATE_CHUNK_LEN: usize = 128;
+/// The image is programmed in pages of this size, the last page is padded with 0xFF
+pub const PAGE_LEN: usize = 256;
+/// Length of a SHA-256 digest
+pub const DIGEST_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No update is in progress
+    NotStarted,
+    /// The image is empty, doesn't fit in the staging area or hasn't arrived completely
+    InvalidLength,
+    /// The CRC of a chunk doesn't match its data
+    InvalidChecksum,
+    /// The SHA-256 of the received image differs from the announced one
+    InvalidImage,
+    /// The image is not signed with the update key
+    InvalidSignature,
+    /// A page reads back different from what was programmed
+    Flash,
+}
+
+/// Where the image is stored until the bootloader takes over. Programming the flash can't
+/// fail on its own, every page is read back to catch a broken flash.
+pub trait FlashWriter {
+    /// Size of the staging area, the largest image that can be received
+    fn capacity(&self) -> u32;
+
+    /// Make sure a previously staged image is not picked up anymore
+    fn unstage(&mut self);
+
+    /// Program a page at `offset` in the staging area. Pages are written in order starting
+    /// at 0, so the flash can be erased as the image comes in.
+    fn program(&mut self, offset: u32, page: &[u8; PAGE_LEN]);
+
+    fn read(&self, offset: u32, buf: &mut [u8]);
+
+    /// Hand the verified image in the staging area to the bootloader
+    fn stage(&mut self, len: u32, digest: &[u8; DIGEST_LEN]);
+}
+
+/// SHA-256 of an image, as announced in [RxCommand::BeginUpdate](crate::RxCommand::BeginUpdate)
+pub fn digest(image: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    digest.copy_from_slice(&Sha256::digest(image));
+
+    digest
+}
+
+struct Transfer {
+    image_len: u32,
+    digest: [u8; DIGEST_LEN],
+    received: u32,
+    hasher: Sha256,
+    /// Page being filled, programmed once it's full or the image is complete
+    page: [u8; PAGE_LEN],
+}
+
+/// Receives an image into a [FlashWriter] and stages it once it's verified
+pub struct Updater {
+    transfer: Option<Transfer>,
+    staged: bool,
+}
+
+impl Updater {
+    pub const fn new() -> Self {
+        Self {
+            transfer: None,
+            staged: false,
+        }
+    }
+
+    pub fn status(&self) -> UpdateStatus {
+        UpdateStatus {
+            received: self.transfer.as_ref().map_or(0, |t| t.received),
+            staged: self.staged,
+        }
+    }
+
+    /// Start receiving a new image, an update in progress or a staged image is dropped
+    pub fn begin(
+        &mut self,
+        flash: &mut impl FlashWriter,
+        image_len: u32,
+        digest: &[u8; DIGEST_LEN],
+    ) -> Result<UpdateStatus, Error> {
+        if image_len == 0 || image_len > flash.capacity() {
+            return Err(Error::InvalidLength);
+        }
+
+        flash.unstage();
+        self.staged = false;
+        self.transfer = Some(Transfer {
+            image_len,
+            digest: *digest,
+            received: 0,
+            hasher: Sha256::new(),
+            page: [0xFF; PAGE_LEN],
+        });
+
+        Ok(self.status())
+    }
+
+    /// Store a chunk. A chunk that doesn't continue where the image ends so far is ignored,
+    /// the returned status tells the host where to continue.
+    pub fn write_chunk(
+        &mut self,
+        flash: &mut impl FlashWriter,
+        offset: u32,
+        crc: u16,
+        data: &[u8; UPDATE_CHUNK_LEN],
+    ) -> Result<UpdateStatus, Error> {
+        let transfer = self.transfer.as_mut().ok_or(Error::NotStarted)?;
+
+        if framing::calc_crc16(data) != crc {
+            return Err(Error::InvalidChecksum);
+        }
+        if offset != transfer.received || offset == transfer.image_len {
+            return Ok(self.status());
+        }
+
+        let len = (transfer.image_len - offset).min(UPDATE_CHUNK_LEN as u32);
+        let data = &data[..len as usize];
+        let page_offset = offset as usize % PAGE_LEN;
+
+        transfer.page[page_offset..page_offset + data.len()].copy_from_slice(data);
+        transfer.hasher.update(data);
+        transfer.received += len;
+
+        if page_offset + data.len() == PAGE_LEN || transfer.received == transfer.image_len {
+            let result = program_page(flash, transfer);
+            if result.is_err() {
+                self.transfer = None;
+            }
+            result?;
+        }
+
+        Ok(self.status())
+    }
+
+    /// Verify the complete image and stage it. A failed verification drops the image.
+    pub fn finish(
+        &mut self,
+        flash: &mut impl FlashWriter,
+        public_key: &[u8; PUBLIC_KEY_LEN],
+        signature: &[u8; SIGNATURE_LEN],
+    ) -> Result<UpdateStatus, Error> {
+        let transfer = self.transfer.as_ref().ok_or(Error::NotStarted)?;
+        if transfer.received != transfer.image_len {
+            return Err(Error::InvalidLength);
+        }
+
+        // the host can only start over from here
+        let transfer = self.transfer.take().unwrap();
+
+        if transfer.hasher.clone().finalize()[..] != transfer.digest[..] {
+            return Err(Error::InvalidImage);
+        }
+
+        let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| Error::InvalidSignature)?;
+        let signature = Signature::try_from(&signature[..]).map_err(|_| Error::InvalidSignature)?;
+        key.verify_digest(transfer.hasher, &signature)
+            .map_err(|_| Error::InvalidSignature)?;
+
+        flash.stage(transfer.image_len, &transfer.digest);
+        self.staged = true;
+
+        Ok(UpdateStatus {
+            received: transfer.image_len,
+            staged: true,
+        })
+    }
+}
+
+impl Default for Updater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Program the page the last chunk went into and start a new one
+fn program_page(flash: &mut impl FlashWriter, transfer: &mut Transfer) -> Result<(), Error> {
+    let offset = (transfer.received - 1) / PAGE_LEN as u32 * PAGE_LEN as u32;
+    flash.program(offset, &transfer.page);
+
+    let mut written = [0u8; PAGE_LEN];
+    flash.read(offset, &mut written);
+
+    let page = transfer.page;
+    transfer.page = [0xFF; PAGE_LEN];
+
+    if written == page {
+        Ok(())
+    } else {
+        Err(Error::Flash)
+    }
+}
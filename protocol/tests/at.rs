@@ -0,0 +1,309 @@
+use gocycling_protocol::at::{
+    self, AtManager, AtPort, BaudRateSwitch, Error, BAUD_FALLBACK_TIMEOUT_US, DEFAULT_BAUD_RATE,
+};
+
+use std::collections::VecDeque;
+
+/// Answers like the bluetooth module, unless told otherwise by the script
+struct FakeModule {
+    /// Baud rate the module is configured with
+    baud_rate: u32,
+    /// Baud rate of the controller side of the UART
+    port_baud_rate: u32,
+    name: Vec<u8>,
+    /// Commands the module understood, in order
+    received: Vec<Vec<u8>>,
+    /// Responses to the next commands, None keeps the module silent. Commands after the
+    /// script ran out are accepted.
+    script: VecDeque<Option<&'static [u8]>>,
+    /// Response bytes waiting to be read
+    pending: VecDeque<u8>,
+    slept_us: Vec<u64>,
+}
+
+impl FakeModule {
+    fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            port_baud_rate: at::BAUD_RATES[0],
+            name: b"BT05".to_vec(),
+            received: Vec::new(),
+            script: VecDeque::new(),
+            pending: VecDeque::new(),
+            slept_us: Vec::new(),
+        }
+    }
+
+    fn script(mut self, responses: &[Option<&'static [u8]>]) -> Self {
+        self.script.extend(responses.iter().copied());
+        self
+    }
+}
+
+impl AtPort for FakeModule {
+    fn write(&mut self, data: &[u8]) {
+        // garbage at another baud rate
+        if self.port_baud_rate != self.baud_rate {
+            return;
+        }
+        self.received.push(data.to_vec());
+
+        match self.script.pop_front() {
+            Some(Some(response)) => self.pending.extend(response.iter().copied()),
+            Some(None) => (),
+            None if data == b"AT+NAME" => {
+                self.pending
+                    .extend(b"OK+NAME=".iter().chain(&self.name).copied());
+            }
+            None => {
+                // `AT+XXXX` is answered with `OK+XXXX`
+                self.pending.extend(b"OK".iter().chain(&data[2..]).copied());
+
+                if let Some(name) = data.strip_prefix(b"AT+NAME=") {
+                    self.name = name.to_vec();
+                }
+                if let Some(baud_rate) = data.strip_prefix(b"AT+BAUD=") {
+                    self.baud_rate = std::str::from_utf8(baud_rate).unwrap().parse().unwrap();
+                }
+            }
+        }
+    }
+
+    fn read_byte(&mut self, _timeout_us: u64) -> Option<u8> {
+        self.pending.pop_front()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.port_baud_rate = baud_rate;
+    }
+
+    fn sleep_us(&mut self, duration_us: u64) {
+        self.slept_us.push(duration_us);
+    }
+}
+
+#[test]
+fn parses_responses() {
+    assert_eq!(at::parse_response(b"OK"), Ok(&b""[..]));
+    assert_eq!(
+        at::parse_response(b"OK+NAME=GoCycling\r\n"),
+        Ok(&b"+NAME=GoCycling"[..])
+    );
+    assert_eq!(at::parse_response(b"ERROR\r\n"), Err(Error::Rejected));
+    assert_eq!(at::parse_response(b""), Err(Error::Rejected));
+}
+
+#[test]
+fn returns_the_response() {
+    let mut at = AtManager::new(FakeModule::new(9600));
+
+    assert_eq!(
+        &at.command(b"AT+NAME=GoCycling").unwrap()[..],
+        b"+NAME=GoCycling"
+    );
+}
+
+#[test]
+fn retries_failed_commands() {
+    let module = FakeModule::new(9600).script(&[None, Some(b"ERROR")]);
+    let mut at = AtManager::new(module);
+
+    assert!(at.command(b"AT+LED2M=1").is_ok());
+    assert_eq!(at.into_port().received.len(), 3);
+}
+
+#[test]
+fn gives_up_after_timeouts() {
+    let module = FakeModule::new(9600).script(&[None, None, None, None]);
+    let mut at = AtManager::new(module);
+
+    assert_eq!(at.command(b"AT+LED2M=1"), Err(Error::Timeout));
+    // the last attempt is not followed by another one
+    assert_eq!(at.into_port().script.len(), 1);
+}
+
+#[test]
+fn gives_up_after_rejections() {
+    let module = FakeModule::new(9600).script(&[Some(&b"ERROR"[..]); 3]);
+    let mut at = AtManager::new(module);
+
+    assert_eq!(at.command(b"AT+LED2M=1"), Err(Error::Rejected));
+}
+
+#[test]
+fn rejects_long_responses() {
+    let module = FakeModule::new(9600).script(&[Some(&[b'O'; 64][..]); 3]);
+    let mut at = AtManager::new(module);
+
+    assert_eq!(at.command(b"AT"), Err(Error::ResponseTooLong));
+}
+
+#[test]
+fn drops_late_responses() {
+    let mut module = FakeModule::new(9600);
+    // the end of an earlier response that timed out
+    module.pending.extend(b"OK+LED2M=1".iter().copied());
+    let mut at = AtManager::new(module);
+
+    assert_eq!(
+        &at.command(b"AT+NAME=GoCycling").unwrap()[..],
+        b"+NAME=GoCycling"
+    );
+}
+
+#[test]
+fn detects_the_baud_rate() {
+    let mut at = AtManager::new(FakeModule::new(57600));
+
+    assert_eq!(at.detect_baud_rate(), Ok(57600));
+    assert_eq!(at.into_port().port_baud_rate, 57600);
+}
+
+#[test]
+fn detection_fails_without_a_module() {
+    let mut at = AtManager::new(FakeModule::new(4800));
+
+    assert_eq!(at.detect_baud_rate(), Err(Error::UnknownBaudRate));
+}
+
+#[test]
+fn changes_the_baud_rate() {
+    let mut at = AtManager::new(FakeModule::new(9600));
+
+    at.change_baud_rate(115200).unwrap();
+
+    let module = at.into_port();
+    assert_eq!(module.received, [b"AT+BAUD=115200".to_vec()]);
+    assert_eq!(module.baud_rate, 115200);
+    assert_eq!(module.port_baud_rate, 115200);
+}
+
+#[test]
+fn keeps_the_baud_rate_when_rejected() {
+    let module = FakeModule::new(9600).script(&[Some(&b"ERROR"[..]); 3]);
+    let mut at = AtManager::new(module);
+
+    assert_eq!(at.change_baud_rate(115200), Err(Error::Rejected));
+    assert_eq!(at.into_port().port_baud_rate, 9600);
+}
+
+#[test]
+fn only_changes_to_supported_baud_rates() {
+    let mut at = AtManager::new(FakeModule::new(9600));
+
+    assert_eq!(at.change_baud_rate(12345), Err(Error::UnsupportedBaudRate));
+    assert!(at.into_port().received.is_empty());
+}
+
+#[test]
+fn sets_up_the_module() {
+    let mut at = AtManager::new(FakeModule::new(9600));
+
+    at.setup().unwrap();
+
+    let module = at.into_port();
+    assert_eq!(
+        module.received,
+        [
+            b"AT+NAME".to_vec(),
+            b"AT+LED2M=1".to_vec(),
+            b"AT+ADVIN=5".to_vec(),
+            b"AT+NAME=GoCycling".to_vec(),
+        ]
+    );
+    // the module needs time after each command
+    assert_eq!(module.slept_us, [1_000_000, 50_000, 50_000]);
+}
+
+#[test]
+fn skips_setup_when_configured() {
+    let mut at = AtManager::new(FakeModule::new(9600));
+    at.setup().unwrap();
+    assert_eq!(at.configured(), Ok(true));
+
+    let mut at = AtManager::new(at.into_port());
+    at.setup().unwrap();
+
+    let module = at.into_port();
+    assert_eq!(module.received.len(), 6);
+    assert_eq!(module.received[5], b"AT+NAME".to_vec());
+    assert_eq!(module.slept_us.len(), 3);
+}
+
+#[test]
+fn setup_stops_at_a_failed_command() {
+    // the name query times out as well
+    let module = FakeModule::new(9600).script(&[None; 6]);
+    let mut at = AtManager::new(module);
+
+    assert_eq!(at.setup(), Err(Error::Timeout));
+
+    let module = at.into_port();
+    assert_eq!(module.received[3..], vec![b"AT+LED2M=1".to_vec(); 3]);
+    // a later boot tries again
+    assert_eq!(module.name, b"BT05");
+}
+
+#[test]
+fn switches_the_baud_rate_when_idle() {
+    let mut switch = BaudRateSwitch::new(DEFAULT_BAUD_RATE);
+    switch.request(115200).unwrap();
+
+    // the module forwards AT commands while a host is connected
+    assert_eq!(switch.poll(0, false), None);
+    assert_eq!(switch.poll(0, true), Some(115200));
+    switch.switched(115200);
+
+    assert_eq!(switch.poll(0, true), None);
+    assert_eq!(switch.baud_rate(), 115200);
+}
+
+#[test]
+fn rejects_unsupported_baud_rates() {
+    let mut switch = BaudRateSwitch::new(DEFAULT_BAUD_RATE);
+
+    assert_eq!(switch.request(12345), Err(Error::UnsupportedBaudRate));
+    assert_eq!(switch.poll(0, true), None);
+}
+
+#[test]
+fn skips_switching_to_the_current_baud_rate() {
+    let mut switch = BaudRateSwitch::new(57600);
+    switch.request(57600).unwrap();
+
+    assert_eq!(switch.poll(0, true), None);
+}
+
+#[test]
+fn falls_back_without_valid_frames() {
+    let mut switch = BaudRateSwitch::new(115200);
+    switch.connected(1_000);
+
+    assert_eq!(
+        switch.poll(1_000 + BAUD_FALLBACK_TIMEOUT_US - 1, false),
+        None
+    );
+    // still connected, the switch waits until the host gave up
+    assert_eq!(switch.poll(1_000 + BAUD_FALLBACK_TIMEOUT_US, false), None);
+    assert_eq!(
+        switch.poll(1_000 + BAUD_FALLBACK_TIMEOUT_US, true),
+        Some(DEFAULT_BAUD_RATE)
+    );
+}
+
+#[test]
+fn keeps_the_baud_rate_after_a_valid_frame() {
+    let mut switch = BaudRateSwitch::new(115200);
+    switch.connected(0);
+    switch.frame_received();
+
+    assert_eq!(switch.poll(2 * BAUD_FALLBACK_TIMEOUT_US, true), None);
+}
+
+#[test]
+fn keeps_the_default_baud_rate_without_valid_frames() {
+    let mut switch = BaudRateSwitch::new(DEFAULT_BAUD_RATE);
+    switch.connected(0);
+
+    assert_eq!(switch.poll(2 * BAUD_FALLBACK_TIMEOUT_US, true), None);
+}
@@ -0,0 +1,43 @@
+use gocycling_protocol::{CycleBatch, MAX_BATCH_LEN};
+
+use proptest::prelude::*;
+
+fn batch(millis: &[u32]) -> CycleBatch {
+    let mut batch = CycleBatch::new();
+    for millis in millis.iter().copied() {
+        assert!(batch.push(millis));
+    }
+    batch
+}
+
+#[test]
+fn encodes_as_varints() {
+    let mut buf = [0u8; 64];
+    let len = batch(&[1000, 1003, 1001]).serialize(&mut buf).unwrap();
+
+    // 1000 zigzag encoded is 2000, the differences are 3 and -2
+    assert_eq!(&buf[..len], [0x03, 0xD0, 0x0F, 0x06, 0x03]);
+}
+
+#[test]
+fn rejects_truncated_batches() {
+    assert!(CycleBatch::deserialize(&[0x02, 0xD0, 0x0F]).is_err());
+    assert!(CycleBatch::deserialize(&[0x01, 0xD0]).is_err());
+}
+
+#[test]
+fn rejects_overlong_varints() {
+    assert!(CycleBatch::deserialize(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x1F]).is_err());
+}
+
+proptest! {
+    #[test]
+    fn roundtrips(millis in prop::collection::vec(any::<u32>(), 0..=MAX_BATCH_LEN)) {
+        let batch = batch(&millis);
+
+        let mut buf = [0u8; 128];
+        let len = batch.serialize(&mut buf).unwrap();
+
+        prop_assert_eq!(CycleBatch::deserialize(&buf[..len]).unwrap(), batch);
+    }
+}
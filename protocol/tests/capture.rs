@@ -0,0 +1,86 @@
+use gocycling_protocol::{
+    capture::{CaptureReader, CaptureRing, Direction, FILE_HEADER_LEN, RECORD_HEADER_LEN},
+    CaptureChunk, CAPTURE_CHUNK_LEN,
+};
+
+use proptest::prelude::*;
+
+const RING_SIZE: usize = 512;
+
+type Record = (Direction, u64, Vec<u8>);
+
+fn record() -> impl Strategy<Value = Record> {
+    (
+        prop_oneof![Just(Direction::Rx), Just(Direction::Tx)],
+        any::<u64>(),
+        prop::collection::vec(any::<u8>(), 0..64),
+    )
+}
+
+/// Read the ring the way the host does, a chunk at a time
+fn download(ring: &CaptureRing<RING_SIZE>) -> Vec<u8> {
+    let mut file = Vec::new();
+
+    loop {
+        let chunk = CaptureChunk::new(file.len() as u16, ring.file_len() as u16, |buf| {
+            ring.read(file.len(), buf)
+        });
+        if chunk.data().is_empty() {
+            return file;
+        }
+
+        file.extend_from_slice(chunk.data());
+    }
+}
+
+proptest! {
+    #[test]
+    fn keeps_newest_records(records in prop::collection::vec(record(), 0..64)) {
+        let mut ring = CaptureRing::<RING_SIZE>::new();
+        for (direction, at_us, data) in &records {
+            prop_assert!(ring.push(*direction, *at_us, data));
+        }
+
+        let file = download(&ring);
+        prop_assert_eq!(file.len(), ring.file_len());
+
+        let read: Vec<Record> = CaptureReader::new(&file)
+            .unwrap()
+            .map(|record| {
+                let record = record.unwrap();
+                (record.direction, record.at_us, record.data.to_vec())
+            })
+            .collect();
+
+        // a suffix of what was pushed, and nothing more could have fit
+        prop_assert_eq!(&records[records.len() - read.len()..], &read[..]);
+        let used = file.len() - FILE_HEADER_LEN;
+        if let Some((_, _, dropped)) = records.iter().rev().nth(read.len()) {
+            prop_assert!(used + RECORD_HEADER_LEN + dropped.len() > RING_SIZE);
+        }
+    }
+
+    #[test]
+    fn chunks_roundtrip(
+        offset in any::<u16>(),
+        total_len in any::<u16>(),
+        data in prop::collection::vec(any::<u8>(), 0..=CAPTURE_CHUNK_LEN),
+    ) {
+        let chunk = CaptureChunk::new(offset, total_len, |buf| {
+            buf[..data.len()].copy_from_slice(&data);
+            data.len()
+        });
+
+        let mut buf = [0u8; 4 + CAPTURE_CHUNK_LEN];
+        let len = chunk.serialize(&mut buf).unwrap();
+
+        prop_assert_eq!(CaptureChunk::deserialize(&buf[..len]).unwrap(), chunk);
+    }
+
+    #[test]
+    fn arbitrary_files_never_panic(file in prop::collection::vec(any::<u8>(), 0..1024)) {
+        if let Ok(reader) = CaptureReader::new(&file) {
+            for _ in reader {}
+        }
+    }
+}
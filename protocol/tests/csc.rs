@@ -0,0 +1,41 @@
+use gocycling_protocol::{CscMeasurement, TxCommand};
+
+use proptest::prelude::*;
+
+#[test]
+fn csc_layout() {
+    let mut measurement = CscMeasurement::new();
+    measurement.add_revolution(500_000);
+    measurement.add_revolution(1_000_000);
+
+    let mut buf = [0u8; CscMeasurement::LEN];
+    assert_eq!(
+        measurement.serialize(&mut buf).unwrap(),
+        CscMeasurement::LEN
+    );
+    assert_eq!(buf, [0x01, 2, 0, 0, 0, 0x00, 0x04]);
+}
+
+#[test]
+fn csc_event_time_wraps() {
+    let mut measurement = CscMeasurement::new();
+
+    // 64 seconds is exactly one wrap of the 1/1024 s counter
+    measurement.add_revolution(64_000_000 + 250_000);
+    assert_eq!(measurement.last_wheel_event, 256);
+}
+
+proptest! {
+    #[test]
+    fn csc_roundtrip(wheel_revolutions in any::<u32>(), last_wheel_event in any::<u16>()) {
+        let measurement = CscMeasurement {
+            wheel_revolutions,
+            last_wheel_event,
+        };
+
+        let mut buf = [0u8; TxCommand::MAX_FRAME_LEN];
+        let len = measurement.serialize(&mut buf).unwrap();
+
+        prop_assert_eq!(CscMeasurement::deserialize(&buf[..len]).unwrap(), measurement);
+    }
+}
@@ -0,0 +1,88 @@
+use gocycling_protocol::{IndoorBikeData, IndoorBikeTracker, TxCommand};
+
+use proptest::prelude::*;
+
+const CYCLE_DISTANCE_MM: u32 = 2_000;
+
+#[test]
+fn indoor_bike_data_layout() {
+    let data = IndoorBikeData {
+        speed: 0x0102,
+        distance: 0x030405,
+        elapsed_time: 0x0607,
+    };
+
+    let mut buf = [0u8; IndoorBikeData::LEN];
+    assert_eq!(data.serialize(&mut buf).unwrap(), IndoorBikeData::LEN);
+    assert_eq!(buf, [0x10, 0x08, 2, 1, 5, 4, 3, 7, 6]);
+}
+
+#[test]
+fn steady_riding() {
+    let mut tracker = IndoorBikeTracker::new(1_000_000);
+
+    // a cycle every half second
+    for i in 1..=20 {
+        tracker.add_cycle(1_000_000 + i * 500_000, 500_000);
+    }
+
+    let data = tracker.record(11_000_000, CYCLE_DISTANCE_MM);
+    // 4 m/s
+    assert_eq!(data.speed, 1_440);
+    assert_eq!(data.distance, 40);
+    assert_eq!(data.elapsed_time, 10);
+}
+
+#[test]
+fn slows_down_and_stops() {
+    let mut tracker = IndoorBikeTracker::new(0);
+    tracker.add_cycle(500_000, 500_000);
+
+    // the next cycle is overdue, so it's going at most half as fast
+    assert_eq!(tracker.record(1_500_000, CYCLE_DISTANCE_MM).speed, 720);
+    assert_eq!(tracker.record(10_000_000, CYCLE_DISTANCE_MM).speed, 0);
+}
+
+#[test]
+fn no_speed_before_the_first_cycle() {
+    let tracker = IndoorBikeTracker::new(0);
+    let data = tracker.record(100_000, CYCLE_DISTANCE_MM);
+
+    assert_eq!((data.speed, data.distance), (0, 0));
+}
+
+proptest! {
+    #[test]
+    fn indoor_bike_data_roundtrip(
+        speed in any::<u16>(),
+        distance in 0..=IndoorBikeData::MAX_DISTANCE,
+        elapsed_time in any::<u16>(),
+    ) {
+        let data = IndoorBikeData {
+            speed,
+            distance,
+            elapsed_time,
+        };
+
+        let mut buf = [0u8; TxCommand::MAX_FRAME_LEN];
+        let len = data.serialize(&mut buf).unwrap();
+
+        prop_assert_eq!(IndoorBikeData::deserialize(&buf[..len]).unwrap(), data);
+    }
+
+    #[test]
+    fn tracker_never_panics(
+        cycles in prop::collection::vec((any::<u64>(), any::<u64>()), 0..64),
+        now_us in any::<u64>(),
+        started_at_us in any::<u64>(),
+        cycle_distance_mm in any::<u32>(),
+    ) {
+        let mut tracker = IndoorBikeTracker::new(started_at_us);
+        for (at_us, cycle_us) in cycles {
+            tracker.add_cycle(at_us, cycle_us);
+        }
+
+        let data = tracker.record(now_us, cycle_distance_mm);
+        prop_assert!(data.distance <= IndoorBikeData::MAX_DISTANCE);
+    }
+}
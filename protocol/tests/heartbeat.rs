@@ -0,0 +1,53 @@
+use gocycling_protocol::heartbeat::Heartbeat;
+
+const TIMEOUT_US: u64 = 5_000_000;
+
+#[test]
+fn stays_alive_while_frames_arrive() {
+    let mut heartbeat = Heartbeat::new();
+    heartbeat.connected(1_000);
+
+    assert!(!heartbeat.poll(1_000 + TIMEOUT_US, TIMEOUT_US));
+    assert!(!heartbeat.frame_received(1_000 + TIMEOUT_US));
+    assert!(!heartbeat.poll(1_000 + 2 * TIMEOUT_US, TIMEOUT_US));
+    assert!(!heartbeat.is_lost());
+}
+
+#[test]
+fn reports_a_lost_heartbeat_once() {
+    let mut heartbeat = Heartbeat::new();
+    heartbeat.connected(0);
+
+    assert!(heartbeat.poll(TIMEOUT_US + 1, TIMEOUT_US));
+    assert!(heartbeat.is_lost());
+    assert!(!heartbeat.poll(2 * TIMEOUT_US, TIMEOUT_US));
+}
+
+#[test]
+fn recovers_when_the_host_sends_again() {
+    let mut heartbeat = Heartbeat::new();
+    heartbeat.connected(0);
+    assert!(heartbeat.poll(TIMEOUT_US + 1, TIMEOUT_US));
+
+    // a ping after the timeout, without the connection pin changing
+    let now = 3 * TIMEOUT_US;
+    assert!(heartbeat.frame_received(now));
+    assert!(!heartbeat.is_lost());
+    assert!(!heartbeat.frame_received(now));
+
+    // the timeout starts over and can be lost again
+    assert!(!heartbeat.poll(now + TIMEOUT_US, TIMEOUT_US));
+    assert!(heartbeat.poll(now + TIMEOUT_US + 1, TIMEOUT_US));
+}
+
+#[test]
+fn starts_over_on_a_new_connection() {
+    let mut heartbeat = Heartbeat::new();
+    heartbeat.connected(0);
+    assert!(heartbeat.poll(TIMEOUT_US + 1, TIMEOUT_US));
+
+    heartbeat.connected(2 * TIMEOUT_US);
+
+    assert!(!heartbeat.is_lost());
+    assert!(!heartbeat.poll(3 * TIMEOUT_US, TIMEOUT_US));
+}
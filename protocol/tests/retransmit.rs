@@ -0,0 +1,124 @@
+use gocycling_protocol::retransmit::RetransmitWindow;
+
+use proptest::prelude::*;
+
+const WINDOW_SIZE: usize = 8;
+const TIMEOUT_US: u64 = 500_000;
+
+type Window = RetransmitWindow<u32, WINDOW_SIZE>;
+
+/// Items that are still waiting for an ack, in the order they are resent
+fn pending(window: &mut Window, now_us: u64) -> Vec<(u8, u32)> {
+    let mut pending = Vec::new();
+    while let Some(frame) = window.next_expired(now_us, TIMEOUT_US) {
+        pending.push(frame);
+    }
+    pending
+}
+
+#[test]
+fn numbers_frames_in_order() {
+    let mut window = Window::new();
+
+    assert_eq!(window.push(10, 0), Some(0));
+    assert_eq!(window.push(11, 0), Some(1));
+    assert_eq!(pending(&mut window, TIMEOUT_US), [(0, 10), (1, 11)]);
+}
+
+#[test]
+fn refuses_frames_when_full() {
+    let mut window = Window::new();
+    for item in 0..WINDOW_SIZE as u32 {
+        assert!(window.push(item, 0).is_some());
+    }
+
+    assert!(window.is_full());
+    assert_eq!(window.push(99, 0), None);
+
+    // the refused frame doesn't use up a sequence number
+    window.ack(0);
+    assert_eq!(window.push(99, 0), Some(WINDOW_SIZE as u8));
+}
+
+#[test]
+fn acks_everything_up_to_the_sequence_number() {
+    let mut window = Window::new();
+    for item in 0..4 {
+        window.push(item, 0);
+    }
+
+    window.ack(1);
+
+    assert_eq!(pending(&mut window, TIMEOUT_US), [(2, 2), (3, 3)]);
+}
+
+#[test]
+fn acks_across_the_wraparound() {
+    let mut window = Window::new();
+    // move the sequence close to the end
+    for _ in 0..254 {
+        let seq = window.push(0, 0).unwrap();
+        window.ack(seq);
+    }
+
+    assert_eq!(window.push(1, 0), Some(254));
+    assert_eq!(window.push(2, 0), Some(255));
+    assert_eq!(window.push(3, 0), Some(0));
+    assert_eq!(window.push(4, 0), Some(1));
+
+    window.ack(255);
+    assert_eq!(pending(&mut window, TIMEOUT_US), [(0, 3), (1, 4)]);
+
+    // an ack from before the wraparound doesn't drop newer frames
+    window.ack(254);
+    assert_eq!(pending(&mut window, 2 * TIMEOUT_US), [(0, 3), (1, 4)]);
+}
+
+#[test]
+fn resends_after_the_timeout() {
+    let mut window = Window::new();
+    window.push(1, 0);
+
+    assert_eq!(window.next_expired(TIMEOUT_US - 1, TIMEOUT_US), None);
+    assert_eq!(window.next_expired(TIMEOUT_US, TIMEOUT_US), Some((0, 1)));
+    // resent just now
+    assert_eq!(window.next_expired(TIMEOUT_US + 1, TIMEOUT_US), None);
+    assert_eq!(window.retransmits(), 1);
+}
+
+#[test]
+fn reset_starts_a_new_sequence() {
+    let mut window = Window::new();
+    window.push(1, 0);
+    window.push(2, 0);
+
+    window.reset();
+
+    assert_eq!(pending(&mut window, TIMEOUT_US), []);
+    assert_eq!(window.push(3, 0), Some(0));
+}
+
+proptest! {
+    #[test]
+    fn keeps_unacked_frames(start in any::<u8>(), count in 1..=WINDOW_SIZE, acked in 0..WINDOW_SIZE) {
+        let mut window = Window::new();
+        for _ in 0..start {
+            let seq = window.push(0, 0).unwrap();
+            window.ack(seq);
+        }
+
+        let seqs: Vec<u8> = (0..count as u32)
+            .map(|item| window.push(item, 0).unwrap())
+            .collect();
+        let acked = acked.min(count - 1);
+        window.ack(seqs[acked]);
+
+        let expected: Vec<(u8, u32)> = seqs
+            .iter()
+            .copied()
+            .zip(0..)
+            .skip(acked + 1)
+            .collect();
+        prop_assert_eq!(pending(&mut window, TIMEOUT_US), expected);
+    }
+}
@@ -0,0 +1,150 @@
+use gocycling_protocol::{
+    framing::{self, FrameFormat},
+    update::{DIGEST_LEN, UPDATE_CHUNK_LEN},
+    Capabilities, Param, RxCommand, RxParser, TxCommand, SECRET_LEN, SIGNATURE_LEN,
+};
+
+use proptest::prelude::*;
+
+const TIMEOUT_US: u64 = 200_000;
+
+fn format() -> impl Strategy<Value = FrameFormat> {
+    prop_oneof![Just(FrameFormat::Crc8), Just(FrameFormat::Crc16)]
+}
+
+fn param() -> impl Strategy<Value = Param> {
+    (0..Param::COUNT as u8).prop_map(|id| Param::from_id(id).unwrap())
+}
+
+/// Arrays larger than proptest supports directly
+fn bytes<const N: usize>() -> impl Strategy<Value = [u8; N]> {
+    prop::collection::vec(any::<u8>(), N).prop_map(|bytes| {
+        let mut array = [0u8; N];
+        array.copy_from_slice(&bytes);
+        array
+    })
+}
+
+fn rx_command() -> impl Strategy<Value = RxCommand> {
+    prop_oneof![
+        Just(RxCommand::StartSession),
+        Just(RxCommand::StopSession),
+        (any::<bool>(), any::<u8>(), any::<u32>()).prop_map(
+            |(session_active, protocol_version, bits)| RxCommand::Handshake {
+                session_active,
+                protocol_version,
+                capabilities: Capabilities::from_bits_truncate(bits),
+            }
+        ),
+        any::<u8>().prop_map(|seq| RxCommand::Ack { seq }),
+        Just(RxCommand::GetLinkStats),
+        Just(RxCommand::GenerateKey),
+        any::<[u8; SECRET_LEN]>().prop_map(|secret| RxCommand::ImportKey { secret }),
+        any::<u32>().prop_map(|code| RxCommand::UnlockProvisioning { code }),
+        Just(RxCommand::GetIdentity),
+        any::<u64>().prop_map(|bits| RxCommand::SetTime { bits }),
+        Just(RxCommand::GetStatus),
+        param().prop_map(|param| RxCommand::GetParam { param }),
+        (param(), any::<u32>()).prop_map(|(param, value)| RxCommand::SetParam { param, value }),
+        any::<u32>().prop_map(|baud_rate| RxCommand::SetBaudRate { baud_rate }),
+        any::<u32>().prop_map(|nonce| RxCommand::Ping { nonce }),
+        any::<u16>().prop_map(|offset| RxCommand::ReadCapture { offset }),
+        (any::<u32>(), bytes::<DIGEST_LEN>())
+            .prop_map(|(image_len, digest)| RxCommand::BeginUpdate { image_len, digest }),
+        (any::<u32>(), any::<u16>(), bytes::<UPDATE_CHUNK_LEN>())
+            .prop_map(|(offset, crc, data)| RxCommand::UpdateChunk { offset, crc, data }),
+        bytes::<SIGNATURE_LEN>().prop_map(|signature| RxCommand::FinishUpdate { signature }),
+    ]
+}
+
+/// The command as it's sent by the host, including the delimiter
+fn encode(cmd: &RxCommand, format: FrameFormat) -> Vec<u8> {
+    let mut frame = [0u8; RxCommand::BUF_SIZE];
+    let frame_len = cmd.serialize(format, &mut frame);
+
+    let mut encoded = vec![0u8; framing::max_encoded_len(frame_len)];
+    let encoded_len = framing::encode(&frame[..frame_len], &mut encoded).unwrap();
+    encoded.truncate(encoded_len);
+
+    encoded
+}
+
+/// Feed all bytes at the same time, returns the result for the last byte
+fn feed(
+    parser: &mut RxParser,
+    bytes: &[u8],
+    now_us: u64,
+    format: Option<FrameFormat>,
+) -> Option<Result<RxCommand, ()>> {
+    let mut last = None;
+
+    for byte in bytes.iter().copied() {
+        last = parser
+            .push(byte, now_us, format)
+            .map(|result| result.map_err(|_| ()));
+    }
+
+    last
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_input_never_panics(
+        input in prop::collection::vec((any::<u8>(), any::<u64>()), 0..2048),
+        format in prop::option::of(format()),
+    ) {
+        let mut parser = RxParser::new(TIMEOUT_US);
+
+        // time is allowed to jump around, the parser must not trust it either
+        for (byte, now_us) in input {
+            parser.push(byte, now_us, format);
+        }
+    }
+
+    #[test]
+    fn commands_roundtrip(cmd in rx_command(), format in format()) {
+        let mut parser = RxParser::new(TIMEOUT_US);
+        let encoded = encode(&cmd, format);
+
+        prop_assert_eq!(feed(&mut parser, &encoded, 0, Some(format)), Some(Ok(cmd)));
+    }
+
+    #[test]
+    fn resyncs_after_delimiter(
+        garbage in prop::collection::vec(any::<u8>(), 0..1024),
+        cmd in rx_command(),
+        format in format(),
+    ) {
+        let mut parser = RxParser::new(TIMEOUT_US);
+        feed(&mut parser, &garbage, 0, None);
+        feed(&mut parser, &[framing::DELIMITER], 0, None);
+
+        let encoded = encode(&cmd, format);
+        prop_assert_eq!(feed(&mut parser, &encoded, 0, Some(format)), Some(Ok(cmd)));
+    }
+
+    #[test]
+    fn resyncs_after_timeout(
+        garbage in prop::collection::vec(1..=u8::MAX, 1..1024),
+        cmd in rx_command(),
+        format in format(),
+    ) {
+        let mut parser = RxParser::new(TIMEOUT_US);
+        prop_assert_eq!(feed(&mut parser, &garbage, 0, None), None);
+
+        let encoded = encode(&cmd, format);
+        prop_assert_eq!(
+            feed(&mut parser, &encoded, TIMEOUT_US + 1, Some(format)),
+            Some(Ok(cmd))
+        );
+        prop_assert_eq!(parser.stale_frames(), 1);
+    }
+
+    #[test]
+    fn tx_commands_never_panic(
+        cmd in any::<u8>(),
+        data in prop::collection::vec(any::<u8>(), 0..TxCommand::MAX_FRAME_LEN),
+    ) {
+        TxCommand::parse(cmd, &data).ok();
+    }
+}
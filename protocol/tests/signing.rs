@@ -0,0 +1,86 @@
+use gocycling_protocol::{signing, Record, SessionSummary, SignedRecord, PUBLIC_KEY_LEN};
+use p256::ecdsa::SigningKey;
+
+/// Reference vector for implementations of the signature check, `openssl dgst -sha256
+/// -verify` accepts [SIGNATURE] over [MESSAGE] with [PUBLIC_KEY] after converting them to
+/// DER
+const SECRET: &str = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20";
+const PUBLIC_KEY: &str = "04515c3d6eb9e396b904d3feca7f54fdcd0cc1e997bf375dca515ad0a6c3b403\
+                          5f4536be3a50f318fbf9a5475902a221502bef0d57e08c53b2cc0a56f17d9f9354";
+/// The record kind followed by the postcard encoded [summary]
+const MESSAGE: &str = "05d204000052aa0800e8030000000000003aae08000000000000000000000000\
+                       000000000000000000ababababababababababababababababababababababab\
+                       ababababababababab";
+const SIGNATURE: &str = "f966ba9726af3d13bab31981e18050656562edc35017eadd453797c932329f17\
+                         87cb64a5b378cb24e4999c21b96fbbb224a6679cde483e80e72ead73fadd52aa";
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn summary() -> SessionSummary {
+    SessionSummary {
+        cycle_count: 1234,
+        millis: 567_890,
+        started_at_ms: 1_000,
+        stopped_at_ms: 568_890,
+        started_at: 0,
+        stopped_at: 0,
+        chain_head: [0xAB; 32],
+    }
+}
+
+fn public_key() -> [u8; PUBLIC_KEY_LEN] {
+    let mut public_key = [0u8; PUBLIC_KEY_LEN];
+    public_key.copy_from_slice(&unhex(PUBLIC_KEY));
+
+    public_key
+}
+
+#[test]
+fn signature_vector() {
+    let key = SigningKey::from_bytes(&unhex(SECRET)).unwrap();
+    assert_eq!(
+        key.verifying_key().to_encoded_point(false).as_bytes(),
+        &public_key()[..]
+    );
+
+    let record = Record::SessionSummary(summary());
+    let mut buf = [0u8; 128];
+    let message_len = record.serialize_message(&mut buf).unwrap();
+    assert_eq!(&buf[..message_len], &unhex(MESSAGE)[..]);
+
+    // signatures are deterministic, so the vector stays the same
+    let signed = signing::sign(&key, record);
+    assert_eq!(&signed.signature[..], &unhex(SIGNATURE)[..]);
+    assert!(signing::verify(&public_key(), &signed));
+}
+
+#[test]
+fn rejects_changed_records() {
+    let mut signature = [0u8; 64];
+    signature.copy_from_slice(&unhex(SIGNATURE));
+
+    let signed = SignedRecord {
+        record: Record::SessionSummary(SessionSummary {
+            cycle_count: 4321,
+            ..summary()
+        }),
+        signature,
+    };
+
+    assert!(!signing::verify(&public_key(), &signed));
+}
+
+#[test]
+fn rejects_other_keys() {
+    let other_key = SigningKey::from_bytes(&[0x17; 32]).unwrap();
+    let signed = signing::sign(&other_key, Record::SessionSummary(summary()));
+
+    assert!(!signing::verify(&public_key(), &signed));
+    // not a point on the curve
+    assert!(!signing::verify(&[0; PUBLIC_KEY_LEN], &signed));
+}
@@ -0,0 +1,94 @@
+use gocycling_protocol::tx_ring::TxRing;
+
+use proptest::prelude::*;
+
+const RING_SIZE: usize = 16;
+
+fn drain(ring: &mut TxRing<RING_SIZE>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while let Some(byte) = ring.pop() {
+        bytes.push(byte);
+    }
+    bytes
+}
+
+#[test]
+fn sends_frames_in_order() {
+    let mut ring = TxRing::<RING_SIZE>::new();
+
+    assert!(ring.push_frame(&[1, 2, 0]));
+    assert!(ring.push_frame(&[3, 0]));
+
+    assert_eq!(ring.free(), RING_SIZE - 5);
+    assert_eq!(drain(&mut ring), [1, 2, 0, 3, 0]);
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn rejects_frames_that_dont_fit() {
+    let mut ring = TxRing::<RING_SIZE>::new();
+    assert!(ring.push_frame(&[1; RING_SIZE - 2]));
+
+    assert!(!ring.push_frame(&[2; 3]));
+    // nothing of the rejected frame was written
+    assert_eq!(ring.free(), 2);
+    assert!(ring.push_frame(&[3; 2]));
+    assert_eq!(ring.free(), 0);
+}
+
+#[test]
+fn wraps_around() {
+    let mut ring = TxRing::<RING_SIZE>::new();
+    ring.push_frame(&[0; RING_SIZE - 4]);
+    drain(&mut ring);
+
+    let frame: Vec<u8> = (1..=10).collect();
+    assert!(ring.push_frame(&frame));
+    assert_eq!(drain(&mut ring), frame);
+}
+
+#[test]
+fn clear_drops_everything() {
+    let mut ring = TxRing::<RING_SIZE>::new();
+    ring.push_frame(&[1, 2, 3]);
+
+    ring.clear();
+
+    assert!(ring.is_empty());
+    assert_eq!(ring.pop(), None);
+    assert_eq!(ring.free(), RING_SIZE);
+}
+
+proptest! {
+    #[test]
+    fn behaves_like_a_queue(
+        ops in prop::collection::vec(
+            prop_oneof![
+                prop::collection::vec(any::<u8>(), 0..8).prop_map(Ok),
+                (0..8usize).prop_map(Err),
+            ],
+            0..64,
+        )
+    ) {
+        let mut ring = TxRing::<RING_SIZE>::new();
+        let mut expected = std::collections::VecDeque::new();
+
+        for op in ops {
+            match op {
+                Ok(frame) => {
+                    let fits = expected.len() + frame.len() <= RING_SIZE;
+                    prop_assert_eq!(ring.push_frame(&frame), fits);
+                    if fits {
+                        expected.extend(frame);
+                    }
+                }
+                Err(count) => {
+                    for _ in 0..count {
+                        prop_assert_eq!(ring.pop(), expected.pop_front());
+                    }
+                }
+            }
+            prop_assert_eq!(ring.free(), RING_SIZE - expected.len());
+        }
+    }
+}
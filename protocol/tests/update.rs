@@ -0,0 +1,284 @@
+use gocycling_protocol::{
+    framing,
+    update::{self, Error, FlashWriter, Updater, DIGEST_LEN, PAGE_LEN, UPDATE_CHUNK_LEN},
+    UpdateStatus, PUBLIC_KEY_LEN, SIGNATURE_LEN,
+};
+use p256::ecdsa::{signature::DigestSigner, Signature, SigningKey};
+use sha2::{Digest, Sha256};
+
+use proptest::prelude::*;
+
+const CAPACITY: u32 = 4 * 4096;
+
+/// Staging area in memory, like the flash it only clears bits when programmed
+struct MemFlash {
+    data: Vec<u8>,
+    staged: Option<(u32, [u8; DIGEST_LEN])>,
+    /// Bits that are stuck at 0, to simulate a broken flash
+    stuck: Option<(u32, u8)>,
+}
+
+impl MemFlash {
+    fn new() -> Self {
+        Self {
+            data: vec![0xFF; CAPACITY as usize],
+            staged: None,
+            stuck: None,
+        }
+    }
+}
+
+impl FlashWriter for MemFlash {
+    fn capacity(&self) -> u32 {
+        CAPACITY
+    }
+
+    fn unstage(&mut self) {
+        self.staged = None;
+        self.data.iter_mut().for_each(|b| *b = 0xFF);
+    }
+
+    fn program(&mut self, offset: u32, page: &[u8; PAGE_LEN]) {
+        assert_eq!(offset as usize % PAGE_LEN, 0);
+
+        let dst = &mut self.data[offset as usize..offset as usize + PAGE_LEN];
+        for (dst, src) in dst.iter_mut().zip(page.iter()) {
+            *dst &= *src;
+        }
+
+        if let Some((stuck_offset, mask)) = self.stuck {
+            self.data[stuck_offset as usize] &= !mask;
+        }
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.data[offset as usize..offset as usize + buf.len()]);
+    }
+
+    fn stage(&mut self, len: u32, digest: &[u8; DIGEST_LEN]) {
+        self.staged = Some((len, *digest));
+    }
+}
+
+fn signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[0x42; 32]).unwrap()
+}
+
+fn public_key(key: &SigningKey) -> [u8; PUBLIC_KEY_LEN] {
+    let mut raw = [0u8; PUBLIC_KEY_LEN];
+    raw.copy_from_slice(key.verifying_key().to_encoded_point(false).as_bytes());
+
+    raw
+}
+
+fn sign(key: &SigningKey, image: &[u8]) -> [u8; SIGNATURE_LEN] {
+    let signature: Signature = key.sign_digest(Sha256::new().chain(image));
+
+    let mut raw = [0u8; SIGNATURE_LEN];
+    raw.copy_from_slice(signature.as_ref());
+
+    raw
+}
+
+fn chunk(image: &[u8], offset: usize) -> (u16, [u8; UPDATE_CHUNK_LEN]) {
+    let end = image.len().min(offset + UPDATE_CHUNK_LEN);
+    let mut data = [0xFF; UPDATE_CHUNK_LEN];
+    data[..end - offset].copy_from_slice(&image[offset..end]);
+
+    (framing::calc_crc16(&data), data)
+}
+
+/// Send the whole image in order, returns the status after the last chunk
+fn send_image(updater: &mut Updater, flash: &mut MemFlash, image: &[u8]) -> UpdateStatus {
+    let mut status = updater
+        .begin(flash, image.len() as u32, &update::digest(image))
+        .unwrap();
+
+    for offset in (0..image.len()).step_by(UPDATE_CHUNK_LEN) {
+        let (crc, data) = chunk(image, offset);
+        status = updater
+            .write_chunk(flash, offset as u32, crc, &data)
+            .unwrap();
+    }
+
+    status
+}
+
+#[test]
+fn stages_a_signed_image() {
+    let key = signing_key();
+    let image: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    send_image(&mut updater, &mut flash, &image);
+
+    let status = updater
+        .finish(&mut flash, &public_key(&key), &sign(&key, &image))
+        .unwrap();
+
+    assert!(status.staged);
+    assert_eq!(flash.staged, Some((1000, update::digest(&image))));
+    assert_eq!(&flash.data[..image.len()], &image[..]);
+}
+
+#[test]
+fn rejects_other_keys() {
+    let key = signing_key();
+    let other_key = SigningKey::from_bytes(&[0x17; 32]).unwrap();
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    send_image(&mut updater, &mut flash, &image);
+
+    assert_eq!(
+        updater.finish(&mut flash, &public_key(&key), &sign(&other_key, &image)),
+        Err(Error::InvalidSignature)
+    );
+    assert_eq!(flash.staged, None);
+    // the image has to be sent again
+    assert_eq!(
+        updater.finish(&mut flash, &public_key(&key), &sign(&key, &image)),
+        Err(Error::NotStarted)
+    );
+}
+
+#[test]
+fn rejects_a_wrong_digest() {
+    let key = signing_key();
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    updater
+        .begin(&mut flash, image.len() as u32, &[0; DIGEST_LEN])
+        .unwrap();
+    for offset in (0..image.len()).step_by(UPDATE_CHUNK_LEN) {
+        let (crc, data) = chunk(&image, offset);
+        updater
+            .write_chunk(&mut flash, offset as u32, crc, &data)
+            .unwrap();
+    }
+
+    assert_eq!(
+        updater.finish(&mut flash, &public_key(&key), &sign(&key, &image)),
+        Err(Error::InvalidImage)
+    );
+    assert_eq!(flash.staged, None);
+}
+
+#[test]
+fn rejects_corrupted_chunks() {
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    updater
+        .begin(&mut flash, image.len() as u32, &update::digest(&image))
+        .unwrap();
+
+    let (crc, mut data) = chunk(&image, 0);
+    data[3] ^= 0x10;
+    assert_eq!(
+        updater.write_chunk(&mut flash, 0, crc, &data),
+        Err(Error::InvalidChecksum)
+    );
+    assert_eq!(updater.status().received, 0);
+}
+
+#[test]
+fn ignores_chunks_out_of_order() {
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    updater
+        .begin(&mut flash, image.len() as u32, &update::digest(&image))
+        .unwrap();
+
+    let (crc, data) = chunk(&image, UPDATE_CHUNK_LEN);
+    let status = updater
+        .write_chunk(&mut flash, UPDATE_CHUNK_LEN as u32, crc, &data)
+        .unwrap();
+    assert_eq!(status.received, 0);
+
+    let (crc, data) = chunk(&image, 0);
+    updater.write_chunk(&mut flash, 0, crc, &data).unwrap();
+    // a repeated chunk is not stored twice
+    let status = updater.write_chunk(&mut flash, 0, crc, &data).unwrap();
+    assert_eq!(status.received, UPDATE_CHUNK_LEN as u32);
+}
+
+#[test]
+fn checks_the_length() {
+    let key = signing_key();
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    let mut updater = Updater::new();
+    assert_eq!(
+        updater.begin(&mut flash, 0, &update::digest(&[])),
+        Err(Error::InvalidLength)
+    );
+    assert_eq!(
+        updater.begin(&mut flash, CAPACITY + 1, &update::digest(&image)),
+        Err(Error::InvalidLength)
+    );
+
+    updater
+        .begin(&mut flash, image.len() as u32, &update::digest(&image))
+        .unwrap();
+    assert_eq!(
+        updater.finish(&mut flash, &public_key(&key), &sign(&key, &image)),
+        Err(Error::InvalidLength)
+    );
+}
+
+#[test]
+fn notices_a_broken_flash() {
+    let image = vec![0x5A; 300];
+
+    let mut flash = MemFlash::new();
+    flash.stuck = Some((1, 0x02));
+    let mut updater = Updater::new();
+    updater
+        .begin(&mut flash, image.len() as u32, &update::digest(&image))
+        .unwrap();
+
+    let (crc, data) = chunk(&image, 0);
+    updater.write_chunk(&mut flash, 0, crc, &data).unwrap();
+    let (crc, data) = chunk(&image, UPDATE_CHUNK_LEN);
+    assert_eq!(
+        updater.write_chunk(&mut flash, UPDATE_CHUNK_LEN as u32, crc, &data),
+        Err(Error::Flash)
+    );
+}
+
+proptest! {
+    #[test]
+    fn images_arrive_intact(
+        image in prop::collection::vec(any::<u8>(), 1..CAPACITY as usize),
+        lost in prop::collection::vec(any::<bool>(), 0..64),
+    ) {
+        let mut flash = MemFlash::new();
+        let mut updater = Updater::new();
+        let mut status = updater
+            .begin(&mut flash, image.len() as u32, &update::digest(&image))
+            .unwrap();
+        let mut lost = lost.into_iter();
+
+        // keep sending from where the controller is, some chunks get lost on the way
+        while (status.received as usize) < image.len() {
+            let (crc, data) = chunk(&image, status.received as usize);
+
+            if lost.next() != Some(true) {
+                status = updater
+                    .write_chunk(&mut flash, status.received, crc, &data)
+                    .unwrap();
+            }
+        }
+
+        prop_assert_eq!(&flash.data[..image.len()], &image[..]);
+    }
+}
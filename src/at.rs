@@ -0,0 +1,52 @@
+//! [AtPort] for the bluetooth module on the host UART, the command logic is in
+//! [gocycling_protocol::at] so it can be tested against a scripted fake module.
+
+use crate::{binding::*, ctypes::c_void};
+
+pub use gocycling_protocol::at::{AtManager, AtPort, BaudRateSwitch, Error, DEFAULT_BAUD_RATE};
+
+/// [AtPort] on a pico UART, must only be used while the UART interrupts are disabled
+pub struct UartPort {
+    uart_dev: *mut c_void,
+}
+
+impl UartPort {
+    pub unsafe fn new(uart_dev: *mut c_void) -> Self {
+        Self { uart_dev }
+    }
+}
+
+impl AtPort for UartPort {
+    fn write(&mut self, data: &[u8]) {
+        unsafe {
+            binding_uart_write_blocking(self.uart_dev, data.as_ptr(), data.len() as u32);
+        }
+    }
+
+    fn read_byte(&mut self, timeout_us: u64) -> Option<u8> {
+        unsafe {
+            let deadline = time_us_64() + timeout_us;
+
+            loop {
+                if binding_uart_is_readable(self.uart_dev) {
+                    return Some(binding_uart_getc(self.uart_dev));
+                }
+                if time_us_64() >= deadline {
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) {
+        unsafe {
+            binding_uart_set_baudrate(self.uart_dev, baud_rate);
+        }
+    }
+
+    fn sleep_us(&mut self, duration_us: u64) {
+        unsafe {
+            sleep_us(duration_us);
+        }
+    }
+}
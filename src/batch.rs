@@ -0,0 +1,49 @@
+use crate::cycling::CycleData;
+
+use gocycling_protocol::CycleBatch;
+
+use core::mem;
+
+/// Live cycles waiting to be sent as a single [CycleBatch]
+#[derive(Clone, Copy)]
+pub struct LiveBatch {
+    cycles: CycleBatch,
+    /// When the first cycle was added
+    opened_at_us: u64,
+}
+
+impl LiveBatch {
+    pub const fn new() -> Self {
+        Self {
+            cycles: CycleBatch::new(),
+            opened_at_us: 0,
+        }
+    }
+
+    /// Returns false if the batch is full
+    pub fn push(&mut self, data: &CycleData, now_us: u64) -> bool {
+        if self.is_empty() {
+            self.opened_at_us = now_us;
+        }
+
+        self.cycles.push(data.millis)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cycles.is_full()
+    }
+
+    /// Whether the first cycle has been waiting for at least `max_age_us`
+    pub fn is_due(&self, now_us: u64, max_age_us: u64) -> bool {
+        !self.is_empty() && now_us.saturating_sub(self.opened_at_us) >= max_age_us
+    }
+
+    /// Take the current cycles, leaving an empty batch
+    pub fn take(&mut self) -> CycleBatch {
+        mem::replace(self, Self::new()).cycles
+    }
+}
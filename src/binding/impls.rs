@@ -12,4 +12,13 @@ impl datetime_t {
             dotw: 0,
         }
     }
+
+    pub fn to_bits(&self) -> u64 {
+        ((self.year as u64 & 0x0FFF) << 0)
+            | ((self.month as u64 & 0x000F) << 12)
+            | ((self.day as u64 & 0x001F) << 16)
+            | ((self.hour as u64 & 0x001F) << 21)
+            | ((self.min as u64 & 0x003F) << 26)
+            | ((self.sec as u64 & 0x003F) << 32)
+    }
 }
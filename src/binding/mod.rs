@@ -2764,6 +2764,18 @@ extern "C" {
 extern "C" {
     pub fn binding_uart_getc(uart: *mut crate::ctypes::c_void) -> u8;
 }
+extern "C" {
+    pub fn binding_uart_is_writable(uart: *mut crate::ctypes::c_void) -> bool;
+}
+extern "C" {
+    pub fn binding_uart_putc_raw(uart: *mut crate::ctypes::c_void, c: u8);
+}
+extern "C" {
+    pub fn binding_uart_set_baudrate(uart: *mut crate::ctypes::c_void, baud_rate: uint) -> uint;
+}
+extern "C" {
+    pub fn binding_uart_tx_wait_blocking(uart: *mut crate::ctypes::c_void);
+}
 extern "C" {
     pub fn binding_irq_set_exclusive_handler(
         irq: uint,
@@ -2800,3 +2812,18 @@ extern "C" {
 extern "C" {
     pub fn binding_restore_interrupts(status: u32);
 }
+extern "C" {
+    pub fn binding_flash_range_erase(flash_offs: u32, count: uint);
+}
+extern "C" {
+    pub fn binding_flash_range_program(flash_offs: u32, data: *const u8, count: uint);
+}
+extern "C" {
+    pub fn binding_flash_contents(flash_offs: u32) -> *const u8;
+}
+extern "C" {
+    pub fn binding_get_unique_board_id(id: *mut u8);
+}
+extern "C" {
+    pub fn binding_rosc_random_byte() -> u8;
+}
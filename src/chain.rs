@@ -0,0 +1,30 @@
+//! Running SHA-256 chain over the cycles of a live session, so the server can detect
+//! cycles that were dropped or injected between the controller and the server.
+//!
+//! The chain starts out as 32 zero bytes, every cycle replaces the head with
+//! `SHA-256(head || millis)` where millis is the little endian u32 of the [CycleData].
+
+use crate::cycling::CycleData;
+
+use gocycling_protocol::HEAD_LEN;
+use sha2::{Digest, Sha256};
+
+#[derive(Clone, Copy, Default)]
+pub struct HashChain {
+    head: [u8; HEAD_LEN],
+}
+
+impl HashChain {
+    pub fn push(&mut self, data: &CycleData) {
+        let digest = Sha256::new()
+            .chain(self.head)
+            .chain(data.millis.to_le_bytes())
+            .finalize();
+
+        self.head.copy_from_slice(&digest);
+    }
+
+    pub fn head(&self) -> [u8; HEAD_LEN] {
+        self.head
+    }
+}
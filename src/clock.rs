@@ -0,0 +1,24 @@
+//! Wall-clock time from the RTC, set by the host. Timestamps are packed the way
+//! [datetime_t::from_bits] expects, so the host can use the same format in both directions.
+
+use crate::{binding::*, critical::CriticalSection};
+
+/// Sent when the RTC was never set since boot
+pub const UNKNOWN_TIME: u64 = 0;
+
+/// Returns false if the packed time is not a valid date
+pub fn set(_: &CriticalSection, bits: u64) -> bool {
+    let mut datetime = datetime_t::from_bits(bits);
+
+    unsafe { rtc_set_datetime(&mut datetime) }
+}
+
+pub fn timestamp() -> u64 {
+    let mut datetime = datetime_t::from_bits(UNKNOWN_TIME);
+
+    if unsafe { rtc_get_datetime(&mut datetime) } {
+        datetime.to_bits()
+    } else {
+        UNKNOWN_TIME
+    }
+}
@@ -1,24 +1,31 @@
-use crate::{binding::*, critical::CriticalSection, host, offline};
-use serde::Serialize;
+use crate::{
+    binding::*,
+    critical::CriticalSection,
+    host, offline,
+    params::{self, Param},
+};
 
-#[derive(Serialize, Clone, Copy, Default)]
-pub struct CycleData {
-    pub millis: u32,
-}
+pub use gocycling_protocol::{CscMeasurement, CycleData, IndoorBikeData, IndoorBikeTracker};
 
 static mut LAST_CYCLE_TIME: u64 = 0;
+/// Counted for all cycles since boot, CSC collectors expect the totals to keep going up
+static mut CSC_MEASUREMENT: CscMeasurement = CscMeasurement::new();
+/// Cycles since the session started
+static mut BIKE_TRACKER: IndoorBikeTracker = IndoorBikeTracker::new(0);
 
 pub fn handle_cycle(cs: &CriticalSection) {
-    const MIN_CYCLE_DELTA: u64 = 50_000;
+    let min_cycle_delta = u64::from(params::get(cs, Param::MinCycleDeltaUs));
 
     let time = unsafe { time_us_64() };
     let delta = time - unsafe { LAST_CYCLE_TIME };
-    if delta < MIN_CYCLE_DELTA {
+    if delta < min_cycle_delta {
         return;
     }
 
     unsafe {
         LAST_CYCLE_TIME = time;
+        CSC_MEASUREMENT.add_revolution(time);
+        BIKE_TRACKER.add_cycle(time, delta);
     }
     let data = CycleData {
         millis: (delta / 1000) as u32,
@@ -33,8 +40,21 @@ pub fn handle_cycle(cs: &CriticalSection) {
     }
 }
 
+/// Wheel revolutions so far, in the CSC Measurement layout
+pub fn csc_measurement(_: &CriticalSection) -> CscMeasurement {
+    unsafe { CSC_MEASUREMENT }
+}
+
+/// Indoor bike data of the session at `now_us`
+pub fn indoor_bike_data(cs: &CriticalSection, now_us: u64) -> IndoorBikeData {
+    let cycle_distance_mm = params::get(cs, Param::CycleDistanceMm);
+
+    unsafe { BIKE_TRACKER.record(now_us, cycle_distance_mm) }
+}
+
 pub fn reset(_: &CriticalSection) {
     unsafe {
         LAST_CYCLE_TIME = time_us_64();
+        BIKE_TRACKER = IndoorBikeTracker::new(LAST_CYCLE_TIME);
     }
 }
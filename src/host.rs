@@ -1,24 +1,61 @@
 use crate::{
+    at::{self, AtManager, AtPort, BaudRateSwitch, UartPort},
+    batch::LiveBatch,
     binding::*,
+    chain::HashChain,
+    clock,
     critical::{self, CriticalSection},
     ctypes::c_void,
     cycling::{self, CycleData},
-    offline::{self, BulkCycleData},
+    identity::{self, KeySource},
+    interrupt, offline,
+    params::{self, Param},
+    signing,
     state::{self, ProgramState},
+    update,
 };
 
 use arrayvec::ArrayVec;
-
-use core::mem;
+use gocycling_protocol::{
+    self as protocol,
+    capture::{CaptureRing, Direction},
+    framing::{self, FrameFormat},
+    heartbeat::Heartbeat,
+    retransmit::RetransmitWindow,
+    tx_ring::TxRing,
+    BaudRateAck, Capabilities, CaptureChunk, ChainCheckpoint, ErrorCode, ErrorReport,
+    HandshakeReply, Identity, LinkStats, ParamValue, Pong, RxCommand, RxParser, SessionSummary,
+    Status, TxCommand, NO_CMD, PROTOCOL_VERSION, RX_INTERBYTE_TIMEOUT_US, UNLOCK_CODE,
+};
+use p256::ecdsa::SigningKey;
 
 pub static mut HOST_INTERFACE: Option<HostInterface> = None;
 
 const CONNECTION_ALARM_NUM: u32 = 1;
-const RECONNECT_TIMEOUT_US: u64 = 10_000_000;
-
-const CONNECTED_HUE: u8 = 160;
-const STARTED_HUE: u8 = 130;
-const RECONNECTING_HUE: u8 = 0;
+const PACING_ALARM_NUM: u32 = 2;
+const RETRANSMIT_TIMEOUT_US: u64 = 500_000;
+/// Amount of cycles between hash chain checkpoints
+const CHAIN_CHECKPOINT_INTERVAL: u32 = 32;
+/// Max time a cycle waits in a live batch before the batch is sent
+const LIVE_BATCH_MAX_AGE_US: u64 = 250_000;
+/// Indoor bike data is sent at 1 Hz, like a fitness machine does
+const INDOOR_BIKE_DATA_INTERVAL_US: u64 = 1_000_000;
+
+/// Capabilities supported by this firmware. Firmware updates are left out until staged
+/// images get installed, see [update].
+const SUPPORTED_CAPABILITIES: Capabilities = Capabilities::from_bits_truncate(
+    Capabilities::OFFLINE_SYNC.bits()
+        | Capabilities::RELIABLE_DELIVERY.bits()
+        | Capabilities::CRC16.bits()
+        | Capabilities::SIGNED_RECORDS.bits()
+        | Capabilities::HASH_CHAIN.bits()
+        | Capabilities::LIVE_BATCHING.bits()
+        | Capabilities::ERROR_REPORTS.bits()
+        | Capabilities::BAUD_SWITCHING.bits()
+        | Capabilities::HEARTBEAT.bits()
+        | Capabilities::CSC_MEASUREMENT.bits()
+        | Capabilities::INDOOR_BIKE_DATA.bits(),
+);
 
 #[derive(Debug)]
 pub enum Error {
@@ -26,6 +63,44 @@ pub enum Error {
     NotStarted,
     NoConnection,
     BufferFull,
+    /// The checksum or encoding of a received frame is wrong
+    InvalidFrame,
+    UnknownCommand,
+    /// A received command has the wrong amount of data
+    InvalidLength,
+    /// A received command contains a value that is out of range
+    InvalidValue,
+    ProvisioningLocked,
+    InvalidKey,
+    /// The bluetooth module didn't accept an AT command
+    ModuleError(at::Error),
+    InvalidChecksum,
+    InvalidImage,
+    InvalidSignature,
+    FlashError,
+}
+
+impl Error {
+    /// Stable code sent to the host in error reports
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::PostcardError(_) => ErrorCode::Serialization,
+            Self::NotStarted => ErrorCode::NotStarted,
+            Self::NoConnection => ErrorCode::NoConnection,
+            Self::BufferFull => ErrorCode::BufferFull,
+            Self::InvalidFrame => ErrorCode::InvalidFrame,
+            Self::UnknownCommand => ErrorCode::UnknownCommand,
+            Self::InvalidLength => ErrorCode::InvalidLength,
+            Self::InvalidValue => ErrorCode::InvalidValue,
+            Self::ProvisioningLocked => ErrorCode::ProvisioningLocked,
+            Self::InvalidKey => ErrorCode::InvalidKey,
+            Self::ModuleError(_) => ErrorCode::ModuleError,
+            Self::InvalidChecksum => ErrorCode::InvalidChecksum,
+            Self::InvalidImage => ErrorCode::InvalidImage,
+            Self::InvalidSignature => ErrorCode::InvalidSignature,
+            Self::FlashError => ErrorCode::FlashError,
+        }
+    }
 }
 
 impl From<postcard::Error> for Error {
@@ -34,158 +109,168 @@ impl From<postcard::Error> for Error {
     }
 }
 
-enum RxCommand {
-    StartSession,
-    StopSession,
-    Handshake { session_active: bool },
-}
-
-impl RxCommand {
-    const BUF_SIZE: usize = 2 + mem::size_of::<Self>();
-    const CMD_START_SESSION: u8 = 1;
-    const CMD_STOP_SESSION: u8 = 2;
-    const CMD_HANDSHAKE: u8 = 3;
-
-    fn expected_len(raw: u8) -> Option<usize> {
-        let data_size = match raw {
-            Self::CMD_START_SESSION => Some(0),
-            Self::CMD_STOP_SESSION => Some(0),
-            Self::CMD_HANDSHAKE => Some(1),
-            _ => None,
-        };
-
-        data_size.map(|size| 2 + size)
+impl From<protocol::Error> for Error {
+    fn from(val: protocol::Error) -> Self {
+        match val {
+            protocol::Error::Postcard(err) => Self::PostcardError(err),
+            protocol::Error::InvalidFrame => Self::InvalidFrame,
+            protocol::Error::UnknownCommand => Self::UnknownCommand,
+            protocol::Error::InvalidLength => Self::InvalidLength,
+            protocol::Error::InvalidValue => Self::InvalidValue,
+        }
     }
+}
 
-    fn deserialize(raw: &[u8]) -> Option<Self> {
-        if raw.len() < 2 || !Self::verify_crc8(raw) {
-            return None;
+impl From<identity::Error> for Error {
+    fn from(val: identity::Error) -> Self {
+        match val {
+            identity::Error::Locked => Self::ProvisioningLocked,
+            identity::Error::InvalidKey => Self::InvalidKey,
         }
+    }
+}
 
-        if let Some(expected_len) = Self::expected_len(raw[0]) {
-            let data = Self::command_data(raw);
-
-            if raw.len() != expected_len {
-                return None;
-            }
-
-            match raw[0] {
-                Self::CMD_START_SESSION => Some(Self::StartSession),
-                Self::CMD_STOP_SESSION => Some(Self::StopSession),
-                Self::CMD_HANDSHAKE => {
-                    let flags = data[0];
-
-                    let session_active = (flags & (1 << 0)) != 0;
-
-                    Some(Self::Handshake { session_active })
-                }
-                // we got an expected len so the cmd should be valid
-                _ => unreachable!(),
-            }
-        } else {
-            None
-        }
+impl From<at::Error> for Error {
+    fn from(val: at::Error) -> Self {
+        Self::ModuleError(val)
     }
+}
 
-    fn verify_crc8(raw: &[u8]) -> bool {
-        if raw.len() < 2 {
-            return false;
+impl From<protocol::update::Error> for Error {
+    fn from(val: protocol::update::Error) -> Self {
+        match val {
+            protocol::update::Error::NotStarted => Self::NotStarted,
+            protocol::update::Error::InvalidLength => Self::InvalidLength,
+            protocol::update::Error::InvalidChecksum => Self::InvalidChecksum,
+            protocol::update::Error::InvalidImage => Self::InvalidImage,
+            protocol::update::Error::InvalidSignature => Self::InvalidSignature,
+            protocol::update::Error::Flash => Self::FlashError,
         }
-
-        let expected = raw[1];
-
-        expected == calc_crc8(Self::command_data(raw))
     }
+}
 
-    fn command_data<'a>(raw: &'a [u8]) -> &'a [u8] {
-        if raw.len() < 2 {
-            &raw[..0]
-        } else {
-            &raw[2..]
+impl From<params::Error> for Error {
+    fn from(val: params::Error) -> Self {
+        match val {
+            params::Error::OutOfRange => Self::InvalidValue,
         }
     }
 }
 
-enum TxCommand {
-    LiveData(CycleData),
-    BulkData(BulkCycleData),
+/// Replace records the backend needs to trust with a signed version
+fn signed(cmd: TxCommand, key: &SigningKey) -> TxCommand {
+    match cmd.record() {
+        Some(record) => TxCommand::SignedRecord(protocol::signing::sign(key, record)),
+        None => cmd,
+    }
 }
 
-impl TxCommand {
-    const CMD_LIVE_DATA: u8 = 1;
-    const CMD_BULK_DATA: u8 = 2;
-
-    fn serialize<'a>(
-        self,
-        buf: &'a mut [u8; mem::size_of::<Self>()],
-    ) -> Result<&'a mut [u8], Error> {
-        let (buf_header, buf_data) = buf.split_at_mut(2);
-
-        let data_len = match self {
-            Self::LiveData(data) => {
-                buf_header[0] = Self::CMD_LIVE_DATA;
-
-                let used = postcard::to_slice(&data, buf_data)?;
-
-                used.len()
-            }
-            Self::BulkData(data) => {
-                buf_header[0] = Self::CMD_BULK_DATA;
-
-                let used = postcard::to_slice(&data, buf_data)?;
-
-                used.len()
-            }
-        };
-
-        buf_header[1] = calc_crc8(&buf_data[..data_len]);
-
-        Ok(&mut buf[..2 + data_len])
-    }
+/// Totals of the live session, used for the summary when the session is stopped
+#[derive(Clone, Copy, Default)]
+struct LiveSession {
+    cycle_count: u32,
+    millis: u32,
+    started_at_ms: u64,
+    started_at: u64,
+    chain: HashChain,
 }
 
 struct Connection {
     connection_lost: bool,
     started: bool,
+    session: LiveSession,
+    /// Features supported by both the host and this firmware
+    capabilities: Capabilities,
+    /// Picked during the handshake, until then frames in any format are accepted
+    frame_format: Option<FrameFormat>,
 }
 
 pub struct HostInterface {
     uart_dev: *mut c_void,
     tx_cmd_bufs: [ArrayVec<TxCommand, { Self::TX_CMD_BUF_SIZE }>; 2],
     cur_tx_cmd_buf: usize,
-    expected_cmd_len: usize,
-    cmd_receive_buffer: ArrayVec<u8, { RxCommand::BUF_SIZE }>,
+    tx_window: RetransmitWindow<TxCommand, { Self::TX_WINDOW_SIZE }>,
+    /// Live cycles waiting to be queued, when batching
+    live_batch: LiveBatch,
+    /// Time the next indoor bike data record is due
+    next_bike_data_at_us: u64,
+    /// Encoded frames waiting to be sent by the TX interrupt
+    tx_ring: TxRing<{ Self::TX_RING_SIZE }>,
+    /// The next frame may not be sent before this time
+    next_frame_at_us: u64,
+    rx_parser: RxParser,
+    /// Traffic on the link, recorded while the capture param is set
+    capture: CaptureRing<{ Self::CAPTURE_SIZE }>,
+    heartbeat: Heartbeat,
+    /// Error report that didn't fit in the tx buffer, sent as soon as there is room
+    pending_error: Option<ErrorReport>,
+    baud_switch: BaudRateSwitch,
+    /// Set while the module is being reconfigured, the UART interrupts stay off meanwhile
+    switching_baud_rate: bool,
+    /// Key the host asked to provision, handled in [HostInterface::update]
+    pending_provision: Option<KeySource>,
+    /// Update step the host asked for, handled in [HostInterface::update]
+    pending_update: Option<update::Request>,
     connection: Option<Connection>,
 }
 
 impl HostInterface {
-    const BAUD_RATE: u32 = 9600;
     const TX_PIN: u32 = 0;
     const RX_PIN: u32 = 1;
 
     const TX_CMD_BUF_SIZE: usize = 64;
-
-    const CMD_SEND_DELAY_MS: u32 = 1;
+    /// Max amount of sent frames waiting for an ack from the host
+    const TX_WINDOW_SIZE: usize = 8;
+    const TX_RING_SIZE: usize = 512;
+    const CAPTURE_SIZE: usize = 4096;
+    /// Received bytes are captured in records of at most this size
+    const RX_CAPTURE_CHUNK_LEN: usize = 32;
+    const MAX_ENCODED_FRAME_LEN: usize = framing::max_encoded_len(TxCommand::MAX_FRAME_LEN);
 
     pub unsafe fn create() {
-        let uart_dev = binding_uart0_init(Self::BAUD_RATE, Self::TX_PIN, Self::RX_PIN);
-
-        // set device name
-        // execute_at_cmd(uart_dev, b"AT+NAME=GoCycling");
-        // the module needs some time to execute the command
-        // it only starts executing after the response it seems
-        // sleep_ms(50);
+        let uart_dev = binding_uart0_init(at::DEFAULT_BAUD_RATE, Self::TX_PIN, Self::RX_PIN);
+
+        // configure the module before the host link takes over the uart
+        let mut at = AtManager::new(UartPort::new(uart_dev));
+        // no interrupts are enabled yet
+        let baud_rate = if interrupt::module_connected(&CriticalSection::new()) {
+            // a connected host would get the AT commands, hope for the default baud rate
+            at::DEFAULT_BAUD_RATE
+        } else {
+            match at.detect_baud_rate() {
+                Ok(baud_rate) => {
+                    // nothing to do when it fails, the module still works with the old settings
+                    at.setup().ok();
+                    baud_rate
+                }
+                Err(_) => {
+                    // the module might just be slow to start, hope for the default baud rate
+                    at.into_port().set_baud_rate(at::DEFAULT_BAUD_RATE);
+                    at::DEFAULT_BAUD_RATE
+                }
+            }
+        };
 
-        // turn off onboard led
-        // execute_at_cmd(uart_dev, b"AT+LED2M=1");
-        // sleep_ms(1000);
+        hardware_alarm_claim(PACING_ALARM_NUM);
+        hardware_alarm_set_callback(PACING_ALARM_NUM, Some(on_pacing_alarm));
 
         HOST_INTERFACE = Some(Self {
             uart_dev,
             tx_cmd_bufs: [ArrayVec::new(), ArrayVec::new()],
             cur_tx_cmd_buf: 0,
-            expected_cmd_len: 0,
-            cmd_receive_buffer: ArrayVec::new(),
+            tx_window: RetransmitWindow::new(),
+            live_batch: LiveBatch::new(),
+            next_bike_data_at_us: 0,
+            tx_ring: TxRing::new(),
+            next_frame_at_us: 0,
+            rx_parser: RxParser::new(RX_INTERBYTE_TIMEOUT_US),
+            capture: CaptureRing::new(),
+            heartbeat: Heartbeat::new(),
+            pending_error: None,
+            baud_switch: BaudRateSwitch::new(baud_rate),
+            switching_baud_rate: false,
+            pending_provision: None,
+            pending_update: None,
             connection: None,
         });
     }
@@ -196,51 +281,386 @@ impl HostInterface {
         let result = match &mut connection {
             Some(Connection {
                 started: true,
+                session,
+                capabilities,
                 ..
             }) => {
+                session.cycle_count = session.cycle_count.saturating_add(1);
+                session.millis = session.millis.saturating_add(data.millis);
+                session.chain.push(&data);
+
+                let checkpoint = if capabilities.contains(Capabilities::HASH_CHAIN)
+                    && session.cycle_count % CHAIN_CHECKPOINT_INTERVAL == 0
+                {
+                    Some(ChainCheckpoint {
+                        cycle_count: session.cycle_count,
+                        head: session.chain.head(),
+                    })
+                } else {
+                    None
+                };
+
                 // in the rare event that the session cannot hold any more cycles,
                 // discard all cycles that do not fit.
                 // the cycles will still be sent over bluetooth
-                self.queue_cmd(cs, TxCommand::LiveData(data))?;
-                Ok(())
+                self.queue_live_data(cs, data, *capabilities)
+                    .and_then(|_| match checkpoint {
+                        Some(checkpoint) => {
+                            // the host needs all cycles up to the checkpoint to verify it
+                            self.flush_live_batch(cs)?;
+                            self.queue_cmd(cs, TxCommand::ChainCheckpoint(checkpoint))
+                        }
+                        None => Ok(()),
+                    })
             }
             Some(Connection { started: false, .. }) => Err(Error::NotStarted),
             None => Err(Error::NoConnection),
         };
 
         self.connection = connection;
+
+        if let Err(Error::BufferFull) = result {
+            self.report_error(cs, NO_CMD, Error::BufferFull);
+        }
+
         result
     }
 
+    fn queue_live_data(
+        &mut self,
+        cs: &CriticalSection,
+        data: CycleData,
+        capabilities: Capabilities,
+    ) -> Result<(), Error> {
+        if capabilities.contains(Capabilities::INDOOR_BIKE_DATA) {
+            // the cycles end up in the next record
+            return Ok(());
+        }
+
+        if capabilities.contains(Capabilities::CSC_MEASUREMENT) {
+            // the measurement carries the totals, a bridge forwards it to the app as is
+            let measurement = cycling::csc_measurement(cs);
+            return self.queue_cmd(cs, TxCommand::CscMeasurement(measurement));
+        }
+
+        if !capabilities.contains(Capabilities::LIVE_BATCHING) {
+            return self.queue_cmd(cs, TxCommand::LiveData(data));
+        }
+
+        // the batch is queued as soon as it's full, so there is always room
+        self.live_batch.push(&data, unsafe { time_us_64() });
+
+        if self.live_batch.is_full() {
+            self.flush_live_batch(cs)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Queue the batched live cycles, if any
+    fn flush_live_batch(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        if self.live_batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.live_batch.take();
+        self.queue_cmd(cs, TxCommand::LiveBatch(batch))
+    }
+
+    /// Queue an indoor bike data record when one is due
+    fn update_bike_data(&mut self, cs: &CriticalSection) {
+        let now = unsafe { time_us_64() };
+        if now < self.next_bike_data_at_us {
+            return;
+        }
+
+        self.next_bike_data_at_us = now + INDOOR_BIKE_DATA_INTERVAL_US;
+
+        let record = cycling::indoor_bike_data(cs, now);
+        if let Err(error) = self.queue_cmd(cs, TxCommand::IndoorBikeData(record)) {
+            self.report_error(cs, NO_CMD, error);
+        }
+    }
+
     fn queue_cmd(&mut self, _: &CriticalSection, cmd: TxCommand) -> Result<(), Error> {
         self.tx_cmd_bufs[self.cur_tx_cmd_buf]
             .try_push(cmd)
             .map_err(|_| Error::BufferFull)
     }
 
+    fn report_error(&mut self, cs: &CriticalSection, cmd: u8, error: Error) {
+        let enabled = self
+            .connection
+            .as_ref()
+            .is_some_and(|c| c.capabilities.contains(Capabilities::ERROR_REPORTS));
+        if !enabled {
+            return;
+        }
+
+        let report = ErrorReport {
+            code: error.code() as u8,
+            cmd,
+        };
+
+        if self.queue_cmd(cs, TxCommand::Error(report)).is_err() {
+            self.pending_error = Some(report);
+        }
+    }
+
     pub fn update(&mut self) {
         if let Some(Connection {
             connection_lost: false,
+            started,
+            capabilities,
+            frame_format,
             ..
         }) = self.connection
         {
+            if let Some(source) = critical::run(|_| self.pending_provision.take()) {
+                self.provision(source);
+            }
+            if let Some(request) = critical::run(|_| self.pending_update.take()) {
+                self.update_firmware(request);
+            }
+
+            let reliable = capabilities.contains(Capabilities::RELIABLE_DELIVERY);
+            let format = frame_format.unwrap_or(FrameFormat::Crc8);
+            let signing_key = if capabilities.contains(Capabilities::SIGNED_RECORDS) {
+                critical::run(signing::device_key)
+            } else {
+                None
+            };
+
+            // don't keep cycles waiting when they are not coming in fast enough to fill a batch
+            critical::run(|cs| {
+                let now = unsafe { time_us_64() };
+
+                if self.live_batch.is_due(now, LIVE_BATCH_MAX_AGE_US) {
+                    self.flush_live_batch(cs).ok();
+                }
+            });
+
+            if started && capabilities.contains(Capabilities::INDOOR_BIKE_DATA) {
+                critical::run(|cs| self.update_bike_data(cs));
+            }
+
+            // acks are handled in the rx interrupt and the ring is drained in the tx
+            // interrupt, so both may only be accessed in a critical section
+            let tx_window = &mut self.tx_window;
+            let tx_ring = &mut self.tx_ring;
+            let capture = &mut self.capture;
+            let has_room = |tx_ring: &mut TxRing<{ Self::TX_RING_SIZE }>| {
+                critical::run(|_| tx_ring.free() >= Self::MAX_ENCODED_FRAME_LEN)
+            };
+
+            if reliable {
+                // first resend everything the host didn't acknowledge in time
+                while has_room(tx_ring) {
+                    let expired = critical::run(|_| {
+                        let now = unsafe { time_us_64() };
+                        tx_window.next_expired(now, RETRANSMIT_TIMEOUT_US)
+                    });
+
+                    match expired {
+                        Some((seq, cmd)) => {
+                            Self::enqueue_frame(tx_ring, capture, format, cmd, Some(seq))
+                        }
+                        None => break,
+                    }
+                }
+            }
+
             // send any pending cmds from the buffer not currently being written to in interrupts
             let last_buf = (self.cur_tx_cmd_buf + 1) % 2;
+            let mut sent = 0;
+
+            for cmd in self.tx_cmd_bufs[last_buf].iter().copied() {
+                // keep the rest of the cmds until there is room to send them
+                if !has_room(tx_ring) || (reliable && critical::run(|_| tx_window.is_full())) {
+                    break;
+                }
+
+                // signing takes a while, so it's done here instead of when queueing the cmd
+                let cmd = match &signing_key {
+                    Some(key) => signed(cmd, key),
+                    None => cmd,
+                };
+
+                let seq = if reliable {
+                    critical::run(|_| {
+                        let now = unsafe { time_us_64() };
+                        tx_window.push(cmd, now)
+                    })
+                } else {
+                    None
+                };
+
+                Self::enqueue_frame(tx_ring, capture, format, cmd, seq);
+                sent += 1;
+            }
+
+            self.tx_cmd_bufs[last_buf].drain(..sent);
+
+            if self.tx_cmd_bufs[last_buf].is_empty() {
+                critical::run(|cs| {
+                    self.cur_tx_cmd_buf = last_buf;
+
+                    // there is room again for the report that didn't fit before
+                    if let Some(report) = self.pending_error.take() {
+                        self.queue_cmd(cs, TxCommand::Error(report)).ok();
+                    }
+                });
+            }
+
+            // start sending, the interrupts take it from here
+            critical::run(|cs| self.drain_tx_ring(cs));
+
+            critical::run(|cs| {
+                let now = unsafe { time_us_64() };
+                let timeout_us = u64::from(params::get(cs, Param::HeartbeatTimeoutUs));
+
+                // the connection pin doesn't tell us when the host app hangs
+                if capabilities.contains(Capabilities::HEARTBEAT)
+                    && self.heartbeat.poll(now, timeout_us)
+                {
+                    self.connection_changed(cs, false);
+                }
+            });
+        }
+
+        // do nothing if not connected, generated commands will accumulate in the buffer
+    }
+
+    /// The ring must have room for a frame of the max size
+    fn enqueue_frame(
+        tx_ring: &mut TxRing<{ Self::TX_RING_SIZE }>,
+        capture: &mut CaptureRing<{ Self::CAPTURE_SIZE }>,
+        format: FrameFormat,
+        cmd: TxCommand,
+        seq: Option<u8>,
+    ) {
+        let mut buf = [0u8; TxCommand::MAX_FRAME_LEN];
+        let used = cmd.serialize(format, seq, &mut buf).unwrap();
+
+        let mut encoded = [0u8; Self::MAX_ENCODED_FRAME_LEN];
+        let encoded_len = framing::encode(used, &mut encoded).unwrap();
+
+        critical::run(|cs| {
+            tx_ring.push_frame(&encoded[..encoded_len]);
+
+            if params::get(cs, Param::Capture) != 0 {
+                capture.push(
+                    Direction::Tx,
+                    unsafe { time_us_64() },
+                    &encoded[..encoded_len],
+                );
+            }
+        });
+    }
+
+    /// Feed the UART from the tx ring until it's empty or the next frame has to wait
+    /// for the pacing gap. The TX interrupt is only enabled while the UART is busy.
+    fn drain_tx_ring(&mut self, cs: &CriticalSection) {
+        if self.switching_baud_rate {
+            return;
+        }
+
+        let mut waiting_for_uart = false;
+        let pacing_us = u64::from(params::get(cs, Param::FramePacingUs));
+
+        unsafe {
+            while !self.tx_ring.is_empty() {
+                let now = time_us_64();
+
+                if now < self.next_frame_at_us {
+                    let target = absolute_time_t {
+                        _private_us_since_boot: self.next_frame_at_us,
+                    };
+
+                    // the pacing alarm continues draining, unless the time already passed
+                    if hardware_alarm_set_target(PACING_ALARM_NUM, target) {
+                        continue;
+                    }
+                    break;
+                }
+
+                if !binding_uart_is_writable(self.uart_dev) {
+                    waiting_for_uart = true;
+                    break;
+                }
+
+                let byte = self.tx_ring.pop().unwrap();
+                binding_uart_putc_raw(self.uart_dev, byte);
 
-            for cmd in self.tx_cmd_bufs[last_buf].drain(..) {
-                let mut buf = [0u8; mem::size_of::<TxCommand>()];
-                let used = cmd.serialize(&mut buf).unwrap();
+                if byte == framing::DELIMITER {
+                    self.next_frame_at_us = now + pacing_us;
+                }
+            }
+
+            binding_uart_set_irq_enables(self.uart_dev, true, waiting_for_uart);
+        }
+    }
 
+    /// Switch to the baud rate the host asked for, or back to the default one when the
+    /// host couldn't reach us. Reconfiguring the module blocks for up to a few seconds, so
+    /// this must be called outside of a critical section.
+    pub fn update_baud_rate(&mut self) {
+        let baud_rate = critical::run(|cs| {
+            let now = unsafe { time_us_64() };
+            // the module forwards AT commands to the host while connected, and a frame that
+            // is still being sent would get garbled
+            let idle = !interrupt::module_connected(cs) && self.tx_ring.is_empty();
+
+            let baud_rate = self.baud_switch.poll(now, idle);
+            if baud_rate.is_some() {
+                self.switching_baud_rate = true;
                 unsafe {
-                    binding_uart_write_blocking(self.uart_dev, used.as_ptr(), used.len() as u32);
-                    sleep_ms(Self::CMD_SEND_DELAY_MS);
+                    // keep the rx interrupt from eating the AT responses
+                    binding_uart_set_irq_enables(self.uart_dev, false, false);
                 }
             }
 
-            critical::run(|_| self.cur_tx_cmd_buf = last_buf);
+            baud_rate
+        });
+
+        if let Some(baud_rate) = baud_rate {
+            let result = self.switch_baud_rate(baud_rate);
+
+            critical::run(|cs| {
+                self.switching_baud_rate = false;
+                unsafe {
+                    binding_uart_set_irq_enables(self.uart_dev, true, false);
+                }
+
+                if let Err(error) = result {
+                    self.report_error(cs, RxCommand::CMD_SET_BAUD_RATE, error.into());
+                }
+            });
         }
+    }
 
-        // do nothing if not connected, generated commands will accumulate in the buffer
+    /// Reconfigure the bluetooth module and the uart, the UART interrupts must be disabled
+    fn switch_baud_rate(&mut self, baud_rate: u32) -> Result<(), at::Error> {
+        unsafe {
+            binding_uart_tx_wait_blocking(self.uart_dev);
+        }
+
+        let mut at = AtManager::new(unsafe { UartPort::new(self.uart_dev) });
+        let mut current = self.baud_switch.baud_rate();
+        let result = at.change_baud_rate(baud_rate).or_else(|_| {
+            // the module might not be at the baud rate we think it is, find it and retry
+            current = at.detect_baud_rate()?;
+            at.change_baud_rate(baud_rate)
+        });
+
+        match result {
+            Ok(()) => current = baud_rate,
+            // stay at the baud rate the module answered at last
+            Err(_) => at.into_port().set_baud_rate(current),
+        }
+        critical::run(|_| self.baud_switch.switched(current));
+
+        result
     }
 
     pub fn has_connection(&self, _: &CriticalSection) -> bool {
@@ -251,13 +671,15 @@ impl HostInterface {
         state::store(
             cs,
             ProgramState::Running {
-                status_hue: RECONNECTING_HUE,
+                status_hue: params::get_hue(cs, Param::ReconnectingHue),
             },
         );
 
+        let reconnect_timeout_us = u64::from(params::get(cs, Param::ReconnectTimeoutUs));
+
         unsafe {
             let connection_gone_time = absolute_time_t {
-                _private_us_since_boot: time_us_64() + RECONNECT_TIMEOUT_US,
+                _private_us_since_boot: time_us_64() + reconnect_timeout_us,
             };
 
             hardware_alarm_claim(CONNECTION_ALARM_NUM);
@@ -269,8 +691,18 @@ impl HostInterface {
     pub fn connection_changed(&mut self, cs: &CriticalSection, value: bool) {
         match self.connection.as_mut() {
             Some(connection) => {
+                // the heartbeat and the connection pin can both report the same change
+                if connection.connection_lost != value {
+                    return;
+                }
                 connection.connection_lost = !value;
 
+                if value {
+                    let now = unsafe { time_us_64() };
+                    self.baud_switch.connected(now);
+                    self.heartbeat.connected(now);
+                }
+
                 if connection.connection_lost {
                     if connection.started {
                         Self::start_reconnecting(cs);
@@ -280,9 +712,9 @@ impl HostInterface {
                     }
                 } else {
                     let hue = if connection.started {
-                        STARTED_HUE
+                        params::get_hue(cs, Param::StartedHue)
                     } else {
-						CONNECTED_HUE
+                        params::get_hue(cs, Param::ConnectedHue)
                     };
 
                     state::store(
@@ -292,12 +724,13 @@ impl HostInterface {
                         },
                     );
 
-                    unsafe {
-                        // cancel alarm, we successfully reconnected
-                        // does nothing if alarm was not set (when a connection is made
-                        // but no session is active)
-                        hardware_alarm_unclaim(CONNECTION_ALARM_NUM);
-                        hardware_alarm_cancel(CONNECTION_ALARM_NUM);
+                    // we reconnected in time, the alarm is only claimed while reconnecting
+                    // to a started session
+                    if connection.started {
+                        unsafe {
+                            hardware_alarm_cancel(CONNECTION_ALARM_NUM);
+                            hardware_alarm_unclaim(CONNECTION_ALARM_NUM);
+                        }
                     }
                 }
             }
@@ -306,33 +739,48 @@ impl HostInterface {
                     for i in 0..2 {
                         self.tx_cmd_bufs[i].clear();
                     }
+                    self.tx_window.reset();
+                    self.live_batch = LiveBatch::new();
+                    self.tx_ring.clear();
+                    self.baud_switch.connected(unsafe { time_us_64() });
                     self.start_online(cs);
+                } else {
+                    // the reconnect alarm leaves rx enabled while the module is still
+                    // connected
+                    self.disable_uart_rx_interrupt();
                 }
             }
         }
     }
 
     fn start_online(&mut self, cs: &CriticalSection) {
+        self.heartbeat.connected(unsafe { time_us_64() });
         self.connection = Some(Connection {
             connection_lost: false,
             started: false,
+            session: LiveSession::default(),
+            capabilities: Capabilities::empty(),
+            frame_format: None,
         });
 
         self.enable_uart_rx_interrupt();
         state::store(
             cs,
             ProgramState::Running {
-                status_hue: CONNECTED_HUE,
+                status_hue: params::get_hue(cs, Param::ConnectedHue),
             },
         );
     }
 
     fn enable_uart_rx_interrupt(&self) {
         unsafe {
-            binding_irq_set_exclusive_handler(UART0_IRQ, Some(on_uart0_rx));
+            binding_irq_set_exclusive_handler(UART0_IRQ, Some(on_uart0_irq));
             binding_irq_set_enabled(UART0_IRQ, true);
 
-            binding_uart_set_irq_enables(self.uart_dev, true, false);
+            // the baud rate switch enables it again when it's done
+            if !self.switching_baud_rate {
+                binding_uart_set_irq_enables(self.uart_dev, true, false);
+            }
         }
     }
 
@@ -342,47 +790,314 @@ impl HostInterface {
         }
     }
 
-    fn execute_rx_cmd(&mut self, cs: &CriticalSection, cmd: RxCommand) {
+    fn rx_cmd_received(
+        &mut self,
+        cs: &CriticalSection,
+        now: u64,
+        cmd: RxCommand,
+    ) -> Result<(), Error> {
+        // the host came back after the heartbeat timed out, the connection is gone when
+        // the reconnect alarm went off in the meantime
+        if self.heartbeat.frame_received(now) || self.connection.is_none() {
+            self.connection_changed(cs, true);
+        }
+
+        // the host can reach us at the current baud rate
+        self.baud_switch.frame_received();
+
+        self.execute_rx_cmd(cs, cmd)
+    }
+
+    fn execute_rx_cmd(&mut self, cs: &CriticalSection, cmd: RxCommand) -> Result<(), Error> {
         match cmd {
             RxCommand::StartSession => self.cmd_start_session(cs),
             RxCommand::StopSession => self.cmd_stop_session(cs),
-            RxCommand::Handshake { session_active } => self.cmd_handshake(cs, session_active),
+            RxCommand::Handshake {
+                session_active,
+                capabilities,
+                ..
+            } => self.cmd_handshake(cs, session_active, capabilities),
+            RxCommand::Ack { seq } => {
+                self.tx_window.ack(seq);
+                Ok(())
+            }
+            RxCommand::GetLinkStats => self.cmd_get_link_stats(cs),
+            // generating a key and writing the flash take a while, so it's done outside of
+            // the interrupt
+            RxCommand::GenerateKey => {
+                self.pending_provision = Some(KeySource::Generate);
+                Ok(())
+            }
+            RxCommand::ImportKey { secret } => {
+                self.pending_provision = Some(KeySource::Import(secret));
+                Ok(())
+            }
+            RxCommand::UnlockProvisioning { code } => {
+                if code != UNLOCK_CODE {
+                    return Err(Error::InvalidValue);
+                }
+
+                identity::unlock(cs);
+                Ok(())
+            }
+            RxCommand::GetIdentity => self.cmd_get_identity(cs),
+            RxCommand::SetTime { bits } => {
+                if !clock::set(cs, bits) {
+                    return Err(Error::InvalidValue);
+                }
+
+                Ok(())
+            }
+            RxCommand::GetStatus => self.cmd_get_status(cs),
+            RxCommand::Ping { nonce } => self.queue_cmd(cs, TxCommand::Pong(Pong { nonce })),
+            RxCommand::GetParam { param } => self.cmd_get_param(cs, param),
+            RxCommand::SetParam { param, value } => {
+                params::set(cs, param, value)?;
+
+                if param == Param::Capture && value != 0 {
+                    self.capture.clear();
+                }
+
+                // confirm the new value
+                self.cmd_get_param(cs, param)
+            }
+            RxCommand::SetBaudRate { baud_rate } => {
+                self.baud_switch
+                    .request(baud_rate)
+                    .map_err(|_| Error::InvalidValue)?;
+
+                self.queue_cmd(cs, TxCommand::BaudRate(BaudRateAck { baud_rate }))
+            }
+            RxCommand::ReadCapture { offset } => self.cmd_read_capture(cs, offset),
+            RxCommand::BeginUpdate { .. }
+            | RxCommand::UpdateChunk { .. }
+            | RxCommand::FinishUpdate { .. }
+                if !self
+                    .connection
+                    .as_ref()
+                    .is_some_and(|c| c.capabilities.contains(Capabilities::FIRMWARE_UPDATE)) =>
+            {
+                Err(Error::UnknownCommand)
+            }
+            // writing the flash and verifying the image take a while, so it's done outside
+            // of the interrupt. The host waits for the status before sending the next step.
+            RxCommand::BeginUpdate { image_len, digest } => {
+                self.pending_update = Some(update::Request::Begin { image_len, digest });
+                Ok(())
+            }
+            RxCommand::UpdateChunk { offset, crc, data } => {
+                self.pending_update = Some(update::Request::Chunk { offset, crc, data });
+                Ok(())
+            }
+            RxCommand::FinishUpdate { signature } => {
+                self.pending_update = Some(update::Request::Finish { signature });
+                Ok(())
+            }
         }
     }
 
-    fn cmd_handshake(&mut self, cs: &CriticalSection, session_active: bool) {
+    fn cmd_handshake(
+        &mut self,
+        cs: &CriticalSection,
+        session_active: bool,
+        capabilities: Capabilities,
+    ) -> Result<(), Error> {
+        if let Some(connection) = self.connection.as_mut() {
+            connection.capabilities = capabilities & SUPPORTED_CAPABILITIES;
+
+            // the reply is already sent in the picked format
+            connection.frame_format = if connection.capabilities.contains(Capabilities::CRC16) {
+                Some(FrameFormat::Crc16)
+            } else {
+                Some(FrameFormat::Crc8)
+            };
+        }
+
+        // frames of an earlier handshake are not acknowledged anymore
+        self.tx_window.reset();
+
+        let reply = HandshakeReply {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: SUPPORTED_CAPABILITIES,
+        };
+        self.queue_cmd(cs, TxCommand::Handshake(reply))?;
+
         if let Some(Connection {
             started: false,
             connection_lost: false,
+            capabilities,
+            ..
         }) = self.connection
         {
             if session_active {
-                if let Some(session) = offline::take_session(cs) {
-                    self.queue_cmd(cs, TxCommand::BulkData(session)).unwrap();
+                if capabilities.contains(Capabilities::OFFLINE_SYNC) {
+                    if let Some(session) = offline::take_session(cs) {
+                        self.queue_cmd(cs, TxCommand::BulkData(session))?;
+                    }
                 }
-                self.cmd_start_session(cs);
+                self.cmd_start_session(cs)?;
             }
         }
+
+        Ok(())
     }
 
-    fn cmd_start_session(&mut self, cs: &CriticalSection) {
-        cycling::reset(cs);
+    fn cmd_get_link_stats(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        let stats = LinkStats {
+            retransmits: self.tx_window.retransmits(),
+            stale_rx_frames: self.rx_parser.stale_frames(),
+        };
 
-        self.connection = Some(Connection {
-            started: true,
-            connection_lost: false,
+        self.queue_cmd(cs, TxCommand::LinkStats(stats))
+    }
+
+    fn cmd_get_status(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        let status = Status {
+            state: state::retrieve(cs),
+            started: self.connection.as_ref().is_some_and(|c| c.started),
+            offline_pending: offline::has_session(cs),
+            tx_buf_lens: [
+                self.tx_cmd_bufs[0].len() as u8,
+                self.tx_cmd_bufs[1].len() as u8,
+            ],
+            uptime_us: unsafe { time_us_64() },
+            battery_level: interrupt::battery_level(cs),
+        };
+
+        self.queue_cmd(cs, TxCommand::Status(status))
+    }
+
+    fn cmd_get_param(&mut self, cs: &CriticalSection, param: Param) -> Result<(), Error> {
+        let value = ParamValue {
+            id: param.id(),
+            value: params::get(cs, param),
+        };
+
+        self.queue_cmd(cs, TxCommand::Param(value))
+    }
+
+    fn cmd_read_capture(&mut self, cs: &CriticalSection, offset: u16) -> Result<(), Error> {
+        // the contents may not change while the host reads them
+        params::set(cs, Param::Capture, 0)?;
+
+        let capture = &self.capture;
+        let chunk = CaptureChunk::new(offset, capture.file_len() as u16, |buf| {
+            capture.read(offset as usize, buf)
+        });
+
+        self.queue_cmd(cs, TxCommand::CaptureChunk(chunk))
+    }
+
+    /// Record bytes received at `at_us`, if capturing
+    fn capture_rx(&mut self, cs: &CriticalSection, at_us: u64, bytes: &[u8]) {
+        if !bytes.is_empty() && params::get(cs, Param::Capture) != 0 {
+            self.capture.push(Direction::Rx, at_us, bytes);
+        }
+    }
+
+    /// Must be called outside of a critical section, see [identity::provision]
+    fn provision(&mut self, source: KeySource) {
+        let cmd = match source {
+            KeySource::Generate => RxCommand::CMD_GENERATE_KEY,
+            KeySource::Import(_) => RxCommand::CMD_IMPORT_KEY,
+        };
+        let result = identity::provision(source);
+
+        critical::run(|cs| {
+            // let the host know the new public key
+            let result = result
+                .map_err(Error::from)
+                .and_then(|_| self.cmd_get_identity(cs));
+
+            if let Err(error) = result {
+                self.report_error(cs, cmd, error);
+            }
+        });
+    }
+
+    /// Must be called outside of a critical section, see [update::handle]
+    fn update_firmware(&mut self, request: update::Request) {
+        let cmd = match request {
+            update::Request::Begin { .. } => RxCommand::CMD_BEGIN_UPDATE,
+            update::Request::Chunk { .. } => RxCommand::CMD_UPDATE_CHUNK,
+            update::Request::Finish { .. } => RxCommand::CMD_FINISH_UPDATE,
+        };
+        let result = update::handle(request);
+
+        critical::run(|cs| {
+            let result = result
+                .map_err(Error::from)
+                .and_then(|status| self.queue_cmd(cs, TxCommand::UpdateStatus(status)));
+
+            if let Err(error) = result {
+                self.report_error(cs, cmd, error);
+            }
         });
+    }
+
+    fn cmd_get_identity(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        let identity = Identity {
+            board_id: identity::board_id(),
+            public_key: signing::public_key(cs),
+        };
+
+        self.queue_cmd(cs, TxCommand::Identity(identity))
+    }
+
+    fn cmd_start_session(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        cycling::reset(cs);
+        self.live_batch = LiveBatch::new();
+        self.next_bike_data_at_us = 0;
+
+        if let Some(connection) = self.connection.as_mut() {
+            connection.started = true;
+            connection.connection_lost = false;
+            connection.session = LiveSession {
+                started_at_ms: unsafe { time_us_64() } / 1000,
+                started_at: clock::timestamp(),
+                ..LiveSession::default()
+            };
+        }
 
         state::store(
             cs,
             ProgramState::Running {
-                status_hue: STARTED_HUE,
+                status_hue: params::get_hue(cs, Param::StartedHue),
             },
         );
+
+        Ok(())
     }
 
-    fn cmd_stop_session(&mut self, _cs: &CriticalSection) {
-        // todo!()
+    fn cmd_stop_session(&mut self, cs: &CriticalSection) -> Result<(), Error> {
+        let summary = match self.connection.as_mut() {
+            Some(connection) if connection.started => {
+                connection.started = false;
+
+                SessionSummary {
+                    cycle_count: connection.session.cycle_count,
+                    millis: connection.session.millis,
+                    started_at_ms: connection.session.started_at_ms,
+                    stopped_at_ms: unsafe { time_us_64() } / 1000,
+                    started_at: connection.session.started_at,
+                    stopped_at: clock::timestamp(),
+                    chain_head: connection.session.chain.head(),
+                }
+            }
+            Some(_) => return Err(Error::NotStarted),
+            None => return Err(Error::NoConnection),
+        };
+
+        state::store(
+            cs,
+            ProgramState::Running {
+                status_hue: params::get_hue(cs, Param::ConnectedHue),
+            },
+        );
+
+        // the summary should come after the last cycles
+        self.flush_live_batch(cs)?;
+        self.queue_cmd(cs, TxCommand::SessionSummary(summary))
     }
 }
 
@@ -394,63 +1109,54 @@ impl Drop for HostInterface {
     }
 }
 
-fn calc_crc8(data: &[u8]) -> u8 {
-    let mut crc = 0xFF;
-
-    for val in data.iter().copied() {
-        crc ^= val;
-        for _ in 0..8 {
-            if (crc & 0x80) != 0 {
-                crc = (crc << 1) ^ 0x31;
-            } else {
-                crc <<= 1;
-            }
-        }
-    }
-
-    crc
-}
-
-/// Must be called only before the global HOST_INTERFACE is started, otherwise the
-/// UART interrupt will interfere
-unsafe fn execute_at_cmd<const S: usize>(uart_dev: *mut c_void, cmd: &[u8; S]) {
-    binding_uart_write_blocking(uart_dev, cmd.as_ptr(), cmd.len() as u32);
-    // response for command AT+XXXX is always OK+XXXX so expect a response the same size
-    // as the command sent
-    let mut buf = [0u8; S];
-    binding_uart_read_blocking(uart_dev, buf.as_mut_ptr(), buf.len() as u32);
-}
-
-unsafe extern "C" fn on_uart0_rx() {
+unsafe extern "C" fn on_uart0_irq() {
     // inside interrupt handler
     let cs = &CriticalSection::new();
 
     if let Some(interface) = HOST_INTERFACE.as_mut() {
+        let mut received = ArrayVec::<u8, { HostInterface::RX_CAPTURE_CHUNK_LEN }>::new();
+        let mut received_at = time_us_64();
+
         while binding_uart_is_readable(interface.uart_dev) {
             let byte = binding_uart_getc(interface.uart_dev);
-            // start receiving new command, discarding the byte if it is not a valid command
-            if interface.cmd_receive_buffer.is_empty() {
-                if let Some(expected) = RxCommand::expected_len(byte) {
-                    interface.expected_cmd_len = expected;
-                } else {
-                    continue;
-                }
+            let now = time_us_64();
+
+            if received.is_full() {
+                interface.capture_rx(cs, received_at, &received);
+                received.clear();
+            }
+            if received.is_empty() {
+                received_at = now;
             }
+            received.push(byte);
 
-            // record the current command...
-            interface.cmd_receive_buffer.push(byte);
+            let format = interface.connection.as_ref().and_then(|c| c.frame_format);
 
-            // ... until we got the expected amount of bytes
-            if interface.cmd_receive_buffer.len() == interface.expected_cmd_len {
-                // and try to deserialize it
-                if let Some(cmd) = RxCommand::deserialize(&interface.cmd_receive_buffer) {
-                    interface.execute_rx_cmd(cs, cmd);
-                }
+            match interface.rx_parser.push(byte, now, format) {
+                Some(Ok(cmd)) => {
+                    let id = cmd.cmd();
 
-                // ready to receive a new command
-                interface.cmd_receive_buffer.clear();
+                    if let Err(error) = interface.rx_cmd_received(cs, now, cmd) {
+                        interface.report_error(cs, id, error);
+                    }
+                }
+                Some(Err(err)) => interface.report_error(cs, err.cmd, err.error.into()),
+                None => (),
             }
         }
+
+        interface.capture_rx(cs, received_at, &received);
+        interface.drain_tx_ring(cs);
+    }
+}
+
+unsafe extern "C" fn on_pacing_alarm(alarm_num: u32) {
+    let cs = &CriticalSection::new();
+
+    if alarm_num == PACING_ALARM_NUM {
+        if let Some(interface) = HOST_INTERFACE.as_mut() {
+            interface.drain_tx_ring(cs);
+        }
     }
 }
 
@@ -459,13 +1165,12 @@ unsafe extern "C" fn on_connection_alarm(alarm_num: u32) {
 
     if alarm_num == CONNECTION_ALARM_NUM {
         if let Some(interface) = HOST_INTERFACE.as_mut() {
-            if interface
-                .connection
-                .as_ref()
-                .map(|c| c.started)
-                .unwrap_or(false)
-            {
-                interface.disable_uart_rx_interrupt();
+            if interface.connection.as_ref().is_some_and(|c| c.started) {
+                // the host can still come back after a lost heartbeat, without an edge
+                // on the connection pin
+                if !interrupt::module_connected(cs) {
+                    interface.disable_uart_rx_interrupt();
+                }
                 interface.connection = None;
                 offline::start(cs);
             }
@@ -0,0 +1,135 @@
+//! Persistent device identity, the P-256 key used for signing records is stored in the
+//! last sector of the flash. Once provisioned, the key can only be replaced after
+//! provisioning was explicitly unlocked.
+
+use crate::{
+    binding::*,
+    critical::{self, CriticalSection},
+    signing,
+};
+
+use gocycling_protocol::{framing, BOARD_ID_LEN, SECRET_LEN};
+use p256::ecdsa::SigningKey;
+use sha2::{Digest, Sha256};
+
+pub const FLASH_SECTOR_SIZE: u32 = 4096;
+pub const FLASH_PAGE_SIZE: usize = 256;
+pub const IDENTITY_FLASH_OFFSET: u32 = PICO_FLASH_SIZE_BYTES - FLASH_SECTOR_SIZE;
+
+const RECORD_MAGIC: u32 = u32::from_le_bytes(*b"GCID");
+/// Magic, secret and checksum
+const RECORD_LEN: usize = 4 + SECRET_LEN + 2;
+
+static mut PROVISIONED: bool = false;
+static mut UNLOCKED: bool = false;
+
+#[derive(Debug)]
+pub enum Error {
+    Locked,
+    InvalidKey,
+}
+
+pub enum KeySource {
+    Generate,
+    Import([u8; SECRET_LEN]),
+}
+
+/// Load the stored key, if any. Must be called once at boot.
+pub unsafe fn init(_: &CriticalSection) {
+    let stored =
+        core::slice::from_raw_parts(binding_flash_contents(IDENTITY_FLASH_OFFSET), RECORD_LEN);
+
+    let key = parse_record(stored).and_then(|secret| SigningKey::from_bytes(&secret).ok());
+    if let Some(key) = key {
+        signing::install_key(key);
+        PROVISIONED = true;
+    }
+}
+
+pub fn unlock(_: &CriticalSection) {
+    unsafe {
+        UNLOCKED = true;
+    }
+}
+
+/// Store a new key and use it for signing from now on. Locks provisioning again.
+///
+/// Generating a key takes a while, so this must not be called from an interrupt.
+/// Interrupts are only disabled while the flash is written.
+pub fn provision(source: KeySource) -> Result<(), Error> {
+    if critical::run(|_| unsafe { PROVISIONED && !UNLOCKED }) {
+        return Err(Error::Locked);
+    }
+
+    let secret = match source {
+        KeySource::Generate => generate_secret(),
+        KeySource::Import(secret) => secret,
+    };
+    let key = SigningKey::from_bytes(&secret).map_err(|_| Error::InvalidKey)?;
+
+    let mut page = [0xFFu8; FLASH_PAGE_SIZE];
+    write_record(&mut page, &secret);
+
+    critical::run(|_| unsafe {
+        // the flash can't be read while it's written, so nothing may run from it
+        binding_flash_range_erase(IDENTITY_FLASH_OFFSET, FLASH_SECTOR_SIZE);
+        binding_flash_range_program(IDENTITY_FLASH_OFFSET, page.as_ptr(), page.len() as u32);
+
+        PROVISIONED = true;
+        UNLOCKED = false;
+    });
+    signing::install_key(key);
+
+    Ok(())
+}
+
+pub fn board_id() -> [u8; BOARD_ID_LEN] {
+    let mut id = [0u8; BOARD_ID_LEN];
+
+    unsafe {
+        binding_get_unique_board_id(id.as_mut_ptr());
+    }
+
+    id
+}
+
+fn generate_secret() -> [u8; SECRET_LEN] {
+    loop {
+        // the random bit of the ring oscillator isn't uniform on its own,
+        // so condition a lot more bits than needed with a hash
+        let mut hasher = Sha256::new();
+        for _ in 0..4 * SECRET_LEN {
+            hasher.update([unsafe { binding_rosc_random_byte() }]);
+        }
+
+        let mut secret = [0u8; SECRET_LEN];
+        secret.copy_from_slice(&hasher.finalize());
+
+        // only fails for the rare values outside of the curve order
+        if SigningKey::from_bytes(&secret).is_ok() {
+            return secret;
+        }
+    }
+}
+
+fn write_record(buf: &mut [u8], secret: &[u8; SECRET_LEN]) {
+    buf[..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+    buf[4..4 + SECRET_LEN].copy_from_slice(secret);
+
+    let crc = framing::calc_crc16(&buf[..4 + SECRET_LEN]);
+    buf[4 + SECRET_LEN..RECORD_LEN].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn parse_record(raw: &[u8]) -> Option<[u8; SECRET_LEN]> {
+    let magic = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+    let crc = u16::from_be_bytes([raw[4 + SECRET_LEN], raw[4 + SECRET_LEN + 1]]);
+
+    if magic != RECORD_MAGIC || crc != framing::calc_crc16(&raw[..4 + SECRET_LEN]) {
+        return None;
+    }
+
+    let mut secret = [0u8; SECRET_LEN];
+    secret.copy_from_slice(&raw[4..4 + SECRET_LEN]);
+
+    Some(secret)
+}
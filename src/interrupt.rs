@@ -4,13 +4,17 @@ const PIN_MAGNET_SENSOR: u32 = 5;
 const PIN_BATTERY_LEVEL_IN: u32 = 26;
 const PIN_CONNECTION_STATE: u32 = 21;
 
-pub unsafe fn init() {
+/// Configure the input pins, [module_connected] can be used after this
+pub unsafe fn init_pins() {
     binding_gpio_set_dir(PIN_MAGNET_SENSOR, false);
     gpio_set_pulls(PIN_MAGNET_SENSOR, true, false);
 
     binding_gpio_set_dir(PIN_CONNECTION_STATE, false);
     // gpio_set_pulls(PIN_CONNECTION_STATE, true, false);
+}
 
+/// Enable the pin interrupts, [init_pins] has to run first
+pub unsafe fn init() {
     gpio_set_irq_enabled(PIN_MAGNET_SENSOR, GPIO_IRQ_EDGE_FALL, true);
     gpio_set_irq_enabled(
         PIN_CONNECTION_STATE,
@@ -24,6 +28,19 @@ pub unsafe fn init() {
     // TODO: battery level adc
 }
 
+/// Battery level as a percentage, if it can be measured
+pub fn battery_level(_: &CriticalSection) -> Option<u8> {
+    // TODO: read PIN_BATTERY_LEVEL_IN once the adc is set up
+    None
+}
+
+/// Whether the bluetooth module is connected to a host, it only takes AT commands without
+/// a connection
+pub fn module_connected(_: &CriticalSection) -> bool {
+    // the state pin is pulled low while connected
+    unsafe { !binding_gpio_get(PIN_CONNECTION_STATE) }
+}
+
 unsafe extern "C" fn on_gpio(pin: u32, events: u32) {
     let rising_edge = (events & GPIO_IRQ_EDGE_RISE) != 0;
     let falling_edge = (events & GPIO_IRQ_EDGE_FALL) != 0;
@@ -1,8 +1,7 @@
 #![no_std]
-#![feature(asm)]
 
 use crate::{binding::*, host::HostInterface, state::ProgramState};
-use core::panic::PanicInfo;
+use core::{arch::asm, panic::PanicInfo};
 
 #[macro_use]
 extern crate bitflags;
@@ -10,13 +9,22 @@ extern crate bitflags;
 mod binding;
 mod ctypes;
 
+mod at;
+mod batch;
+mod chain;
+mod clock;
 mod critical;
 mod cycling;
 mod host;
+mod identity;
 mod interrupt;
 mod offline;
+mod params;
 mod rgb;
+mod signing;
 mod state;
+mod tick;
+mod update;
 
 const PIN_STATUS_LED_R: u32 = 6;
 const PIN_STATUS_LED_G: u32 = 7;
@@ -30,12 +38,17 @@ const PIN_BATTERY_LED_B: u32 = 4;
 pub unsafe extern "C" fn main() -> ! {
     // sleep_ms(MODULES_STARTUP_MS);
 
+    interrupt::init_pins();
     HostInterface::create();
+    // no interrupts are enabled yet
+    identity::init(&critical::CriticalSection::new());
     rtc_init();
     interrupt::init();
+    tick::init();
 
     let status_led = rgb::RgbLed::new(PIN_STATUS_LED_R, PIN_STATUS_LED_G, PIN_STATUS_LED_B);
     let mut old_status_hue: u8 = 0;
+    let mut old_brightness = rgb::max_brightness();
     let battery_led: rgb::RgbLed =
         rgb::RgbLed::new(PIN_BATTERY_LED_R, PIN_BATTERY_LED_G, PIN_BATTERY_LED_B);
 
@@ -43,6 +56,8 @@ pub unsafe extern "C" fn main() -> ! {
 
     let mut rainbow_hue = 0;
     loop {
+        host.update_baud_rate();
+
         let state = critical::run(|cs| state::retrieve(cs));
         match state {
             ProgramState::WaitForModeSelect => {
@@ -62,9 +77,12 @@ pub unsafe extern "C" fn main() -> ! {
                 });
             }
             ProgramState::Running { status_hue } => {
-                if status_hue != old_status_hue {
+                let brightness = rgb::max_brightness();
+
+                if status_hue != old_status_hue || brightness != old_brightness {
                     status_led.put_rainbow_hue(status_hue);
                     old_status_hue = status_hue;
+                    old_brightness = brightness;
                 }
 
                 if critical::run(|cs| host.has_connection(cs)) {
@@ -1,49 +1,20 @@
 use crate::{
+    clock,
     critical::CriticalSection,
     cycling::{self, CycleData},
+    params::{self, Param},
     state::{self, ProgramState},
 };
+use gocycling_protocol::BulkCycleData;
 use serde::Serialize;
 
 static mut CURRENT_BULK: Option<BulkCycleData> = None;
 
-const OFFLINE_MODE_HUE: u8 = 190;
-
 pub enum Error {
     BulkFull,
     NotActive,
 }
 
-#[derive(Serialize, Clone, Copy)]
-pub struct BulkCycleData {
-    millis: u32,
-    cycle_count: u16,
-    // session_flags: SessionFlags,
-}
-
-impl BulkCycleData {
-    pub const fn new() -> Self {
-        Self {
-            millis: 0,
-            cycle_count: 0,
-            // session_flags: SessionFlags::empty(),
-        }
-    }
-
-    pub fn add_cycle(&mut self, data: &CycleData) -> Result<(), Error> {
-        let millis_result = self.millis.overflowing_add(data.millis);
-
-        if self.cycle_count == u16::MAX || millis_result.1 {
-            return Err(Error::BulkFull);
-        }
-
-        self.cycle_count += 1;
-        self.millis = millis_result.0;
-
-        Ok(())
-    }
-}
-
 bitflags! {
     #[derive(Serialize)]
     struct SessionFlags: u8 {
@@ -54,8 +25,11 @@ bitflags! {
 
 pub fn add_cycle(_: &CriticalSection, data: &CycleData) -> Result<(), Error> {
     if let Some(bulk) = unsafe { CURRENT_BULK.as_mut() } {
-        bulk.add_cycle(data)?;
-        Ok(())
+        if bulk.add_cycle(data) {
+            Ok(())
+        } else {
+            Err(Error::BulkFull)
+        }
     } else {
         Err(Error::NotActive)
     }
@@ -63,7 +37,7 @@ pub fn add_cycle(_: &CriticalSection, data: &CycleData) -> Result<(), Error> {
 
 pub fn start(cs: &CriticalSection) {
     unsafe {
-        CURRENT_BULK = Some(BulkCycleData::new());
+        CURRENT_BULK = Some(BulkCycleData::new(clock::timestamp()));
     }
 
     cycling::reset(cs);
@@ -71,11 +45,15 @@ pub fn start(cs: &CriticalSection) {
     state::store(
         cs,
         ProgramState::Running {
-            status_hue: OFFLINE_MODE_HUE,
+            status_hue: params::get_hue(cs, Param::OfflineModeHue),
         },
     );
 }
 
+pub fn has_session(_: &CriticalSection) -> bool {
+    unsafe { CURRENT_BULK.is_some() }
+}
+
 pub fn take_session(_: &CriticalSection) -> Option<BulkCycleData> {
     unsafe { CURRENT_BULK.take() }
 }
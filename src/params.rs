@@ -0,0 +1,123 @@
+//! Tuning values that can be changed by the host at runtime, so different bikes and
+//! trainers don't need a different firmware. Values are not persisted, every boot starts
+//! with the defaults.
+
+use crate::critical::CriticalSection;
+
+use core::cell::UnsafeCell;
+
+pub use gocycling_protocol::Param;
+
+struct ParamInfo {
+    default: u32,
+    min: u32,
+    max: u32,
+}
+
+const PARAM_COUNT: usize = Param::COUNT;
+
+const fn hue(default: u8) -> ParamInfo {
+    ParamInfo {
+        default: default as u32,
+        min: 0,
+        max: u8::MAX as u32,
+    }
+}
+
+const fn info(param: Param) -> ParamInfo {
+    match param {
+        Param::MinCycleDeltaUs => ParamInfo {
+            default: 50_000,
+            min: 10_000,
+            max: 1_000_000,
+        },
+        Param::ReconnectTimeoutUs => ParamInfo {
+            default: 10_000_000,
+            min: 1_000_000,
+            max: 60_000_000,
+        },
+        Param::FramePacingUs => ParamInfo {
+            default: 1_000,
+            min: 0,
+            max: 100_000,
+        },
+        Param::MaxBrightness => ParamInfo {
+            default: 0x0CFF,
+            min: 0,
+            max: u16::MAX as u32,
+        },
+        Param::ConnectedHue => hue(160),
+        Param::StartedHue => hue(130),
+        Param::ReconnectingHue => hue(0),
+        Param::OfflineModeHue => hue(190),
+        Param::HeartbeatTimeoutUs => ParamInfo {
+            default: 5_000_000,
+            min: 500_000,
+            max: 60_000_000,
+        },
+        Param::Capture => ParamInfo {
+            default: 0,
+            min: 0,
+            max: 1,
+        },
+        // a 700x25c wheel
+        Param::CycleDistanceMm => ParamInfo {
+            default: 2_105,
+            min: 100,
+            max: 10_000,
+        },
+    }
+}
+
+static VALUES: ValuesWrapper = ValuesWrapper(UnsafeCell::new(default_values()));
+
+pub enum Error {
+    OutOfRange,
+}
+
+/// Evaluated while compiling, so a default outside of its bounds fails the build
+const fn default_values() -> [u32; PARAM_COUNT] {
+    let mut values = [0; PARAM_COUNT];
+
+    let mut id = 0;
+    while id < PARAM_COUNT {
+        let info = match Param::from_id(id as u8) {
+            Some(param) => info(param),
+            None => panic!("param ids must be below Param::COUNT"),
+        };
+        assert!(info.min <= info.default && info.default <= info.max);
+
+        values[id] = info.default;
+        id += 1;
+    }
+
+    values
+}
+
+pub fn get(_: &CriticalSection, param: Param) -> u32 {
+    unsafe { (*VALUES.0.get())[param as usize] }
+}
+
+/// Get a hue param, these are always in range of a u8
+pub fn get_hue(cs: &CriticalSection, param: Param) -> u8 {
+    get(cs, param) as u8
+}
+
+pub fn set(_: &CriticalSection, param: Param, value: u32) -> Result<(), Error> {
+    let info = info(param);
+    if value < info.min || value > info.max {
+        return Err(Error::OutOfRange);
+    }
+
+    unsafe {
+        (*VALUES.0.get())[param as usize] = value;
+    }
+
+    Ok(())
+}
+
+struct ValuesWrapper(UnsafeCell<[u32; PARAM_COUNT]>);
+
+/// We can implement this because it's a single threaded environment and can only
+/// be accessed publically through the get/set functions
+unsafe impl Sync for ValuesWrapper {}
@@ -1,6 +1,8 @@
-use crate::binding::*;
-
-pub const MAX_BRIGHTNESS: u16 = 0x0CFF;
+use crate::{
+    binding::*,
+    critical,
+    params::{self, Param},
+};
 
 pub struct RgbLed {
     r_pin: u32,
@@ -35,16 +37,17 @@ impl RgbLed {
 
     pub fn put_rainbow_hue(&self, hue: u8) {
         let (r, g, b) = hue_to_rgb_rainbow(hue);
-        let (r, g, b) = scale_rgb(r, g, b);
+        let (r, g, b) = scale_rgb(r, g, b, max_brightness());
         self.put_rgb(r, g, b);
     }
 
-    // Values are clamped with a max of [MAX_BRIGHTNESS]
+    // Values are clamped with a max of [Param::MaxBrightness]
     pub fn put_rgb(&self, r: u16, g: u16, b: u16) {
         // limit the max brightness of the led
-        let r = r.clamp(0, MAX_BRIGHTNESS);
-        let g = g.clamp(0, MAX_BRIGHTNESS);
-        let b = b.clamp(0, MAX_BRIGHTNESS);
+        let max = max_brightness();
+        let r = r.clamp(0, max);
+        let g = g.clamp(0, max);
+        let b = b.clamp(0, max);
 
         unsafe {
             // we're using a common anode led, so the level needs to be
@@ -124,11 +127,15 @@ fn hue_to_rgb_rainbow(hue: u8) -> (u8, u8, u8) {
     }
 }
 
-fn scale_rgb(r: u8, g: u8, b: u8) -> (u16, u16, u16) {
+pub fn max_brightness() -> u16 {
+    critical::run(|cs| params::get(cs, Param::MaxBrightness)) as u16
+}
+
+fn scale_rgb(r: u8, g: u8, b: u8, max: u16) -> (u16, u16, u16) {
     (
-        u16::from(r) * u16::max(1, MAX_BRIGHTNESS / u16::from(u8::MAX)),
-        u16::from(g) * u16::max(1, MAX_BRIGHTNESS / u16::from(u8::MAX)),
-        u16::from(b) * u16::max(1, MAX_BRIGHTNESS / u16::from(u8::MAX)),
+        u16::from(r) * u16::max(1, max / u16::from(u8::MAX)),
+        u16::from(g) * u16::max(1, max / u16::from(u8::MAX)),
+        u16::from(b) * u16::max(1, max / u16::from(u8::MAX)),
     )
 }
 
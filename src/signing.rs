@@ -0,0 +1,33 @@
+//! The per-device key records the backend needs to trust are signed with, see
+//! [gocycling_protocol::signing] for what is signed.
+
+use crate::critical::{self, CriticalSection};
+
+use gocycling_protocol::PUBLIC_KEY_LEN;
+use p256::ecdsa::SigningKey;
+
+static mut DEVICE_KEY: Option<SigningKey> = None;
+/// Uncompressed SEC1 encoding of the public half of [DEVICE_KEY]
+static mut PUBLIC_KEY: Option<[u8; PUBLIC_KEY_LEN]> = None;
+
+/// Use `key` for signing records from now on. Deriving the public key takes a while, so
+/// this must not be called from an interrupt.
+pub fn install_key(key: SigningKey) {
+    let point = key.verifying_key().to_encoded_point(false);
+    let mut public_key = [0u8; PUBLIC_KEY_LEN];
+    public_key.copy_from_slice(point.as_bytes());
+
+    critical::run(|_| unsafe {
+        DEVICE_KEY = Some(key);
+        PUBLIC_KEY = Some(public_key);
+    });
+}
+
+pub fn public_key(_: &CriticalSection) -> Option<[u8; PUBLIC_KEY_LEN]> {
+    unsafe { PUBLIC_KEY }
+}
+
+/// Get a copy of the device key, so records can be signed outside of a critical section
+pub fn device_key(_: &CriticalSection) -> Option<SigningKey> {
+    unsafe { DEVICE_KEY.clone() }
+}
@@ -1,13 +1,9 @@
 use crate::critical::CriticalSection;
 use core::cell::UnsafeCell;
 
-static STATE: StateWrapper = StateWrapper(UnsafeCell::new(ProgramState::WaitForModeSelect));
+pub use gocycling_protocol::ProgramState;
 
-#[derive(Clone, Copy)]
-pub enum ProgramState {
-    WaitForModeSelect,
-    Running { status_hue: u8 },
-}
+static STATE: StateWrapper = StateWrapper(UnsafeCell::new(ProgramState::WaitForModeSelect));
 
 /// Get a copy of the current program state
 pub fn retrieve(_cs: &CriticalSection) -> ProgramState {
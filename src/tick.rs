@@ -0,0 +1,28 @@
+//! Periodic alarm that wakes the main loop, for work that has to happen after some time
+//! even when no other interrupts occur.
+
+use crate::binding::*;
+
+const TICK_ALARM_NUM: u32 = 0;
+const TICK_INTERVAL_US: u64 = 50_000;
+
+pub unsafe fn init() {
+    hardware_alarm_claim(TICK_ALARM_NUM);
+    hardware_alarm_set_callback(TICK_ALARM_NUM, Some(on_tick));
+    schedule();
+}
+
+unsafe fn schedule() {
+    let next_tick = absolute_time_t {
+        _private_us_since_boot: time_us_64() + TICK_INTERVAL_US,
+    };
+
+    hardware_alarm_set_target(TICK_ALARM_NUM, next_tick);
+}
+
+unsafe extern "C" fn on_tick(alarm_num: u32) {
+    // nothing to do here, the interrupt itself wakes up the main loop
+    if alarm_num == TICK_ALARM_NUM {
+        schedule();
+    }
+}
@@ -0,0 +1,135 @@
+//! Firmware updates received from the host, see [gocycling_protocol::update]. The image is
+//! written to the second half of the flash, the running firmware stays untouched. Once it's
+//! verified a header is written to the sector before the identity:
+//!
+//! | Field  | Size | Description                                  |
+//! |--------|------|----------------------------------------------|
+//! | magic  | 4    | `GCUP`                                       |
+//! | len    | 4    | Little endian length of the image            |
+//! | digest | 32   | SHA-256 of the image                         |
+//! | crc    | 2    | Big endian CRC-16 of the fields above        |
+//!
+//! This firmware only stages images. Copying a staged image over the firmware has to
+//! happen before it runs, from RAM and in a way that survives losing power halfway, so
+//! that's left to a second stage bootloader that checks the header. Without one a staged
+//! image is never installed, so the update commands are rejected until there is one.
+//!
+//! The public key images are checked against is read at build time from the file
+//! `GOCYCLING_UPDATE_KEY` points to, it holds the 65 byte uncompressed SEC1 key.
+
+use crate::{
+    binding::*,
+    critical,
+    identity::{FLASH_PAGE_SIZE, FLASH_SECTOR_SIZE, IDENTITY_FLASH_OFFSET},
+};
+
+use gocycling_protocol::{
+    framing,
+    update::{self, FlashWriter, Updater, DIGEST_LEN, PAGE_LEN, UPDATE_CHUNK_LEN},
+    UpdateStatus, PUBLIC_KEY_LEN, SIGNATURE_LEN,
+};
+
+/// Uncompressed SEC1 public key of the key release images are signed with. The build
+/// fails when the file is missing or has another length.
+const UPDATE_PUBLIC_KEY: [u8; PUBLIC_KEY_LEN] = *include_bytes!(env!(
+    "GOCYCLING_UPDATE_KEY",
+    "GOCYCLING_UPDATE_KEY must be the absolute path of the update public key"
+));
+
+const HEADER_FLASH_OFFSET: u32 = IDENTITY_FLASH_OFFSET - FLASH_SECTOR_SIZE;
+const STAGING_FLASH_OFFSET: u32 = PICO_FLASH_SIZE_BYTES / 2;
+
+const HEADER_MAGIC: u32 = u32::from_le_bytes(*b"GCUP");
+/// Magic, length, digest and checksum
+const HEADER_LEN: usize = 4 + 4 + DIGEST_LEN + 2;
+
+static mut UPDATER: Updater = Updater::new();
+
+/// An update step the host asked for, see [handle]
+pub enum Request {
+    Begin {
+        image_len: u32,
+        digest: [u8; DIGEST_LEN],
+    },
+    Chunk {
+        offset: u32,
+        crc: u16,
+        data: [u8; UPDATE_CHUNK_LEN],
+    },
+    Finish {
+        signature: [u8; SIGNATURE_LEN],
+    },
+}
+
+struct StagingFlash;
+
+impl FlashWriter for StagingFlash {
+    fn capacity(&self) -> u32 {
+        HEADER_FLASH_OFFSET - STAGING_FLASH_OFFSET
+    }
+
+    fn unstage(&mut self) {
+        critical::run(|_| unsafe {
+            binding_flash_range_erase(HEADER_FLASH_OFFSET, FLASH_SECTOR_SIZE);
+        });
+    }
+
+    fn program(&mut self, offset: u32, page: &[u8; PAGE_LEN]) {
+        let flash_offset = STAGING_FLASH_OFFSET + offset;
+
+        critical::run(|_| unsafe {
+            // pages come in order, so a sector is erased right before its first page
+            if flash_offset.is_multiple_of(FLASH_SECTOR_SIZE) {
+                binding_flash_range_erase(flash_offset, FLASH_SECTOR_SIZE);
+            }
+
+            binding_flash_range_program(flash_offset, page.as_ptr(), page.len() as u32);
+        });
+    }
+
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let contents = unsafe {
+            core::slice::from_raw_parts(
+                binding_flash_contents(STAGING_FLASH_OFFSET + offset),
+                buf.len(),
+            )
+        };
+
+        buf.copy_from_slice(contents);
+    }
+
+    fn stage(&mut self, len: u32, digest: &[u8; DIGEST_LEN]) {
+        let mut page = [0xFFu8; FLASH_PAGE_SIZE];
+        page[..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        page[4..8].copy_from_slice(&len.to_le_bytes());
+        page[8..8 + DIGEST_LEN].copy_from_slice(digest);
+
+        let crc = framing::calc_crc16(&page[..8 + DIGEST_LEN]);
+        page[8 + DIGEST_LEN..HEADER_LEN].copy_from_slice(&crc.to_be_bytes());
+
+        // erased when the update began
+        critical::run(|_| unsafe {
+            binding_flash_range_program(HEADER_FLASH_OFFSET, page.as_ptr(), page.len() as u32);
+        });
+    }
+}
+
+/// Handle an update step. Writing the flash and verifying the image take a while, so this
+/// must not be called from an interrupt. Interrupts are only disabled while the flash is
+/// written.
+pub fn handle(request: Request) -> Result<UpdateStatus, update::Error> {
+    // only ever used here, from the main loop
+    let updater = unsafe { &mut UPDATER };
+
+    match request {
+        Request::Begin { image_len, digest } => {
+            updater.begin(&mut StagingFlash, image_len, &digest)
+        }
+        Request::Chunk { offset, crc, data } => {
+            updater.write_chunk(&mut StagingFlash, offset, crc, &data)
+        }
+        Request::Finish { signature } => {
+            updater.finish(&mut StagingFlash, &UPDATE_PUBLIC_KEY, &signature)
+        }
+    }
+}